@@ -1,12 +1,77 @@
-use serde::Deserialize;
+use std::{collections::BTreeMap, env, fmt, fs, marker::PhantomData, path::Path};
 
-#[derive(Debug, Deserialize, Clone)]
+use clap::ArgEnum;
+use derive_more::Display;
+use k8s_openapi::api::core::v1::{
+    Affinity, Container, EnvVar, Toleration, TopologySpreadConstraint, Volume, VolumeMount,
+};
+
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+        MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer, Serialize,
+};
+
+use ron::ser::PrettyConfig;
+use serde_json::Value;
+
+use crate::SugarfungeChainType;
+
+/// Amounts of cpu, memory and ephemeral storage, in the Kubernetes quantity notation.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceQuantities {
+    pub cpu: Option<String>,
+    pub memory: Option<String>,
+    pub ephemeral_storage: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourcesConfig {
+    pub requests: Option<ResourceQuantities>,
+    pub limits: Option<ResourceQuantities>,
+}
+
+/// Where the pods of a component run, rendered into the pod template as written.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulingConfig {
+    pub node_selector: Option<BTreeMap<String, String>>,
+    pub tolerations: Option<Vec<Toleration>>,
+    pub affinity: Option<Affinity>,
+    // Constraints without a label selector select the pods of every instance of the component
+    pub topology_spread: Option<Vec<TopologySpreadConstraint>>,
+}
+
+/// Settings of the pods of a component, in its `pod` field. The env, args and volume mounts go to
+/// the main container.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PodConfig {
+    pub resources: Option<ResourcesConfig>,
+    pub scheduling: Option<SchedulingConfig>,
+    // Existing pull secrets used instead of the registry secret
+    pub image_pull_secrets: Option<Vec<String>>,
+    pub extra_env: Option<Vec<EnvVar>>,
+    pub extra_args: Option<Vec<String>>,
+    pub extra_volumes: Option<Vec<Volume>>,
+    pub extra_volume_mounts: Option<Vec<VolumeMount>>,
+    pub sidecars: Option<Vec<Container>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct ApiConfig {
     pub name: String,
     pub image: String,
     pub port: i32,
     pub listen_url: String,
     pub node_url: String,
+    pub pod: PodConfig,
 }
 
 impl Default for ApiConfig {
@@ -17,16 +82,19 @@ impl Default for ApiConfig {
             port: 4000,
             listen_url: "http://0.0.0.0:4000".to_string(),
             node_url: "ws://sf-node:9944".to_string(),
+            pod: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct ExplorerConfig {
     pub name: String,
     pub image: String,
     pub port: i32,
     pub ws_url: String,
+    pub pod: PodConfig,
 }
 
 impl Default for ExplorerConfig {
@@ -36,11 +104,13 @@ impl Default for ExplorerConfig {
             image: "sugarfunge.azurecr.io/explorer:latest".to_string(),
             port: 80,
             ws_url: "wss://node.sugarfunge.dev".to_string(),
+            pod: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct IpfsConfig {
     pub name: String,
     pub image: String,
@@ -48,6 +118,7 @@ pub struct IpfsConfig {
     pub swarm_udp_port: i32,
     pub api_port: i32,
     pub swarm_key: Option<String>,
+    pub pod: PodConfig,
 }
 
 impl Default for IpfsConfig {
@@ -59,11 +130,13 @@ impl Default for IpfsConfig {
             swarm_udp_port: 4002,
             api_port: 5001,
             swarm_key: None,
+            pod: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct KeycloakDatabaseConfig {
     pub db_database: String,
     pub db_user: String,
@@ -86,7 +159,8 @@ impl Default for KeycloakDatabaseConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct KeycloakConfig {
     pub name: String,
     pub image: String,
@@ -94,6 +168,7 @@ pub struct KeycloakConfig {
     pub admin_username: String,
     pub admin_password: String,
     pub db_config: KeycloakDatabaseConfig,
+    pub pod: PodConfig,
 }
 
 impl Default for KeycloakConfig {
@@ -105,11 +180,13 @@ impl Default for KeycloakConfig {
             admin_username: "keycloak".to_string(),
             admin_password: "keycloak".to_string(),
             db_config: Default::default(),
+            pod: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct BootNode {
     pub dns_url: Option<String>,
     pub dns_ip: Option<String>,
@@ -128,23 +205,152 @@ impl Default for BootNode {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct ChainSpecExternal {
     pub wget_image: String,
     pub chainspec_url: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl Default for ChainSpecExternal {
+    fn default() -> Self {
+        Self {
+            wget_image: "busybox".to_string(),
+            chainspec_url: "".to_string(),
+        }
+    }
+}
+
+/// RPC methods exposed by the node, `Auto` being `Safe` when the RPC is external.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Display)]
+pub enum RpcMethods {
+    Auto,
+    Safe,
+    Unsafe,
+}
+
+/// How the node exposes its RPC. The defaults are the safe settings of a public node.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    pub methods: RpcMethods,
+    // Origins allowed to call the RPC, `all` for any, the node defaults to localhost when omitted
+    pub cors: Option<Vec<String>>,
+    pub max_connections: Option<u32>,
+    // Listen on every interface, required to reach the RPC through the service
+    pub ws_external: bool,
+    pub rpc_external: bool,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            methods: RpcMethods::Safe,
+            cors: None,
+            max_connections: None,
+            ws_external: true,
+            rpc_external: false,
+        }
+    }
+}
+
+impl RpcConfig {
+    /// The RPC of the local chain is fully open for development, other chains get the defaults.
+    pub fn for_chain(chain: SugarfungeChainType) -> Self {
+        match chain {
+            SugarfungeChainType::Local => Self {
+                methods: RpcMethods::Unsafe,
+                cors: Some(vec!["all".to_string()]),
+                max_connections: None,
+                ws_external: true,
+                rpc_external: true,
+            },
+            SugarfungeChainType::Testnet => Self::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Display)]
+pub enum P2pServiceType {
+    NodePort,
+    LoadBalancer,
+}
+
+/// Service reaching the P2P port of the node pod from outside the cluster.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct P2pServiceConfig {
+    pub service_type: P2pServiceType,
+    // Allocated by the cluster when omitted
+    pub node_port: Option<i32>,
+    // DNS name or IPv4 address of the service announced with `--public-addr`
+    pub public_host: Option<String>,
+}
+
+impl Default for P2pServiceConfig {
+    fn default() -> Self {
+        Self {
+            service_type: P2pServiceType::NodePort,
+            node_port: None,
+            public_host: None,
+        }
+    }
+}
+
+/// Secret URIs of the session keys of a validator, inserted into its keystore before it starts.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionKeysConfig {
+    // sr25519 key producing the blocks
+    pub aura: String,
+    // ed25519 key voting on the finality
+    pub grandpa: String,
+}
+
+/// Persistent volume of the chain data and keystore, claimed for each node pod.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeStorageConfig {
+    // The default storage class of the cluster when omitted
+    pub storage_class: Option<String>,
+    pub size: String,
+    // Passed to the node as `--base-path`
+    pub mount_path: String,
+}
+
+impl Default for NodeStorageConfig {
+    fn default() -> Self {
+        Self {
+            storage_class: None,
+            size: "20Gi".to_string(),
+            mount_path: "/data".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct NodeConfig {
     pub name: String,
     pub image: String,
     pub ws_port: i32,
+    pub rpc_port: i32,
     pub p2p_port: i32,
     pub prometheus_port: i32,
     pub node_name: String,
     pub chainspec_file_name: Option<String>,
     pub chainspec_ext: Option<ChainSpecExternal>,
     pub bootnode: Option<BootNode>,
+    // Hex encoded ed25519 secret key of the libp2p identity, the other nodes use it as bootnode
+    pub node_key: Option<String>,
+    // The settings of the chain type when omitted
+    pub rpc: Option<RpcConfig>,
+    pub p2p_service: Option<P2pServiceConfig>,
+    // Runs the node as a validator with these keys
+    pub session_keys: Option<SessionKeysConfig>,
+    // The chain data is lost with the pod when `None`
+    pub storage: Option<NodeStorageConfig>,
+    pub pod: PodConfig,
 }
 
 impl Default for NodeConfig {
@@ -153,22 +359,31 @@ impl Default for NodeConfig {
             name: "sf-node".to_string(),
             image: "sugarfunge.azurecr.io/node:latest".to_string(),
             ws_port: 9944,
+            rpc_port: 9933,
             p2p_port: 30334,
             prometheus_port: 9090,
             node_name: "alice".to_string(),
             chainspec_file_name: Some("customSpec.json".to_string()),
             chainspec_ext: None,
             bootnode: None,
+            node_key: None,
+            rpc: None,
+            p2p_service: None,
+            session_keys: None,
+            storage: Some(Default::default()),
+            pod: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct StatusConfig {
     pub name: String,
     pub image: String,
     pub port: i32,
     pub node_url: String,
+    pub pod: PodConfig,
 }
 
 impl Default for StatusConfig {
@@ -178,11 +393,13 @@ impl Default for StatusConfig {
             image: "sugarfunge.azurecr.io/status:latest".to_string(),
             port: 8000,
             node_url: "wss://node.sugarfunge.dev".to_string(),
+            pod: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct IngressConfig {
     pub name: String,
     pub host: String,
@@ -201,27 +418,1679 @@ impl Default for IngressConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Formats of the config files.
+#[derive(ArgEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Ron,
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// The format matching the extension of the file, RON for any other extension.
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("yaml") | Some("yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            Some("json") => Self::Json,
+            _ => Self::Ron,
+        }
+    }
+
+    /// Parses a config file into a layer for `Config::from_layers`. Syntax errors report the line
+    /// and column, and invalid values the path of the field.
+    pub fn parse(self, contents: &str) -> anyhow::Result<Value> {
+        let layer = match self {
+            Self::Ron => ron_layer(contents)?,
+            Self::Yaml => serde_yaml::from_str(contents)?,
+            Self::Toml => toml::from_str(contents)?,
+            Self::Json => serde_json::from_str(contents)?,
+        };
+
+        // Every layer is checked on its own, so an invalid value is reported with its file.
+        if layer.is_object() {
+            serde_path_to_error::deserialize::<_, Config>(normalize_sections(layer.clone())?)?;
+        }
+
+        Ok(layer)
+    }
+}
+
+/// Reads a RON config into a layer. RON struct fields only deserialize as identifiers and
+/// `ron::Value` reads enum variants as unit, so the variants are quoted into strings first, and
+/// unit values become empty structs, as in `emptyDir: Some(())`.
+fn ron_layer(contents: &str) -> anyhow::Result<Value> {
+    let parse = |contents: &str| {
+        ron::from_str::<ron::Value>(contents).map_err(|e| {
+            anyhow::anyhow!(
+                "{} at line {} column {}",
+                e.code,
+                e.position.line,
+                e.position.col
+            )
+        })
+    };
+
+    // Syntax errors are reported on the file as written, so the positions match it.
+    parse(contents)?;
+
+    ron_to_json(parse(&quote_variants(contents))?)
+}
+
+/// Quotes the bare identifiers of a RON file that are not struct names, field names or
+/// keywords, the unit enum variants like `Safe`.
+fn quote_variants(contents: &str) -> String {
+    let chars: Vec<char> = contents.chars().collect();
+    let mut output = String::with_capacity(contents.len());
+    let mut i = 0;
+
+    // Copies the characters up to `end`, excluded.
+    let copy = |output: &mut String, from: usize, end: usize| {
+        output.extend(&chars[from..end.min(chars.len())]);
+    };
+
+    while i < chars.len() {
+        let start = i;
+
+        match chars[i] {
+            '"' | '\'' => {
+                let quote = chars[i];
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                copy(&mut output, start, i + 1);
+                i += 1;
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                copy(&mut output, start, i);
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let mut depth = 0;
+                while i < chars.len() {
+                    if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                copy(&mut output, start, i);
+            }
+            // Extensions like `#![enable(implicit_some)]`
+            '#' => {
+                while i < chars.len() && chars[i] != ']' {
+                    i += 1;
+                }
+                copy(&mut output, start, i + 1);
+                i += 1;
+            }
+            // Numbers, so exponents and suffixes are not read as identifiers
+            x if x.is_ascii_digit() => {
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || "_.".contains(chars[i]))
+                {
+                    i += 1;
+                }
+                copy(&mut output, start, i);
+            }
+            x if x.is_ascii_alphabetic() || x == '_' => {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let ident: String = chars[start..i].iter().collect();
+
+                // Raw and byte strings
+                if ["r", "b", "br"].contains(&ident.as_str())
+                    && matches!(chars.get(i), Some('"' | '#'))
+                {
+                    let hashes = chars[i..].iter().take_while(|x| **x == '#').count();
+                    let end: String = std::iter::once('"')
+                        .chain("#".repeat(hashes).chars())
+                        .collect();
+                    let body_start = (i + hashes + 1).min(chars.len());
+                    let body: String = chars[body_start..].iter().collect();
+                    i = match body.find(&end) {
+                        Some(position) => {
+                            body_start + body[..position].chars().count() + end.chars().count()
+                        }
+                        None => chars.len(),
+                    };
+                    copy(&mut output, start, i);
+                    continue;
+                }
+
+                let next = chars[i..].iter().find(|x| !x.is_whitespace());
+                let keyword =
+                    ["true", "false", "None", "Some", "inf", "NaN"].contains(&ident.as_str());
+
+                if keyword || matches!(next, Some('(' | ':')) {
+                    output.push_str(&ident);
+                } else {
+                    output.push('"');
+                    output.push_str(&ident);
+                    output.push('"');
+                }
+            }
+            x => {
+                output.push(x);
+                i += 1;
+            }
+        }
+    }
+
+    output
+}
+
+fn ron_to_json(value: ron::Value) -> anyhow::Result<Value> {
+    Ok(match value {
+        ron::Value::Bool(x) => Value::Bool(x),
+        ron::Value::Char(x) => Value::String(x.to_string()),
+        ron::Value::String(x) => Value::String(x),
+        ron::Value::Number(ron::Number::Integer(x)) => Value::from(x),
+        ron::Value::Number(ron::Number::Float(x)) => Value::from(x.get()),
+        ron::Value::Option(None) => Value::Null,
+        ron::Value::Option(Some(x)) => ron_to_json(*x)?,
+        ron::Value::Unit => Value::Object(Default::default()),
+        ron::Value::Seq(x) => {
+            Value::Array(x.into_iter().map(ron_to_json).collect::<Result<_, _>>()?)
+        }
+        ron::Value::Map(x) => {
+            let mut fields = serde_json::Map::new();
+            for (key, value) in x.iter() {
+                match key {
+                    ron::Value::String(key) => {
+                        fields.insert(key.to_string(), ron_to_json(value.clone())?);
+                    }
+                    key => anyhow::bail!("map keys must be strings, found {:?}", key),
+                }
+            }
+            Value::Object(fields)
+        }
+    })
+}
+
+/// Components that can have several named instances in the config.
+pub trait Instance {
+    fn name(&self) -> &str;
+}
+
+macro_rules! impl_instance {
+    ($($config:ty),*) => {
+        $(
+            impl Instance for $config {
+                fn name(&self) -> &str {
+                    &self.name
+                }
+            }
+        )*
+    };
+}
+
+impl_instance!(
+    ApiConfig,
+    ExplorerConfig,
+    IpfsConfig,
+    KeycloakConfig,
+    NodeConfig,
+    StatusConfig,
+    IngressConfig,
+    RegistryConfig
+);
+
+/// The instances with the given name, or all of them when no name is given.
+pub fn select<'a, T: Instance + 'a>(
+    instances: impl IntoIterator<Item = &'a T>,
+    name: Option<&str>,
+) -> Vec<&'a T> {
+    instances
+        .into_iter()
+        .filter(|x| name.is_none_or(|name| x.name() == name))
+        .collect()
+}
+
+struct InstancesVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for InstancesVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("None, Some(instance), an instance or a list of instances")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(vec![])
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(vec![])
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        Vec::deserialize(SeqAccessDeserializer::new(seq))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        Ok(vec![T::deserialize(MapAccessDeserializer::new(map))?])
+    }
+}
+
+/// Component sections accept the single instance forms, `None` and `Some(..)`, besides a list
+/// of instances, so the config files written for one instance per component keep working.
+fn instances<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    deserializer.deserialize_any(InstancesVisitor(PhantomData))
+}
+
+/// Credentials of the private registry of the images, written to a pull secret used by every
+/// pod without its own `image_pull_secrets`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistryConfig {
+    pub name: String,
+    pub server: String,
+    pub username: String,
+    pub password: String,
+    pub email: Option<String>,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            name: "sf-registry".to_string(),
+            server: "sugarfunge.azurecr.io".to_string(),
+            username: "".to_string(),
+            password: "".to_string(),
+            email: None,
+        }
+    }
+}
+
+/// Settings shared by every object of the stack, so several stacks can share a cluster.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StackConfig {
+    // Prepended to the name of every instance and to the references to them
+    pub prefix: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub api: Option<ApiConfig>,
-    pub explorer: Option<ExplorerConfig>,
-    pub ipfs: Option<IpfsConfig>,
-    pub keycloak: Option<KeycloakConfig>,
-    pub node: Option<NodeConfig>,
-    pub status: Option<StatusConfig>,
+    pub context: Option<String>,
+    pub stack: Option<StackConfig>,
+    pub registry: Option<RegistryConfig>,
+    #[serde(deserialize_with = "instances", alias = "apis")]
+    pub api: Vec<ApiConfig>,
+    #[serde(deserialize_with = "instances", alias = "explorers")]
+    pub explorer: Vec<ExplorerConfig>,
+    #[serde(deserialize_with = "instances")]
+    pub ipfs: Vec<IpfsConfig>,
+    #[serde(deserialize_with = "instances", alias = "keycloaks")]
+    pub keycloak: Vec<KeycloakConfig>,
+    #[serde(deserialize_with = "instances", alias = "nodes")]
+    pub node: Vec<NodeConfig>,
+    #[serde(deserialize_with = "instances", alias = "statuses")]
+    pub status: Vec<StatusConfig>,
     pub ingress: Option<IngressConfig>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            api: Some(Default::default()),
-            explorer: Some(Default::default()),
-            ipfs: Some(Default::default()),
-            keycloak: Some(Default::default()),
-            node: Some(Default::default()),
-            status: Some(Default::default()),
+            context: None,
+            stack: None,
+            registry: None,
+            api: vec![Default::default()],
+            explorer: vec![Default::default()],
+            ipfs: vec![Default::default()],
+            keycloak: vec![Default::default()],
+            node: vec![Default::default()],
+            status: vec![Default::default()],
             ingress: Some(Default::default()),
         }
     }
 }
+
+impl Config {
+    /// Defaults for the chain type. The testnet node loads its chainspec from the secret named
+    /// after the node and connects to the bootnode, and the RPC settings of the chain are
+    /// written out.
+    pub fn for_chain(chain: SugarfungeChainType) -> Self {
+        let mut config = Self::default();
+
+        if chain == SugarfungeChainType::Testnet {
+            config.node = vec![NodeConfig {
+                bootnode: Some(Default::default()),
+                ..Default::default()
+            }];
+        }
+
+        for node in &mut config.node {
+            node.rpc = Some(RpcConfig::for_chain(chain));
+        }
+
+        config
+    }
+
+    /// Deep-merges the layers, base config first, field by field on top of the defaults.
+    pub fn from_layers(layers: Vec<Value>) -> anyhow::Result<Self> {
+        let defaults = serde_json::to_value(Self::default())?;
+        let mut config = defaults.clone();
+        // The default instances of a section are replaced by the first layer listing instances.
+        let mut listed: Vec<&str> = vec![];
+
+        for layer in layers {
+            let mut layer = match layer {
+                // An empty YAML file
+                Value::Null => continue,
+                Value::Object(_) => normalize_sections(layer)?,
+                _ => anyhow::bail!("the config must be a struct or a map"),
+            };
+
+            for (section, _) in INSTANCE_SECTIONS {
+                let overlay = match layer.as_object_mut().and_then(|x| x.remove(section)) {
+                    Some(Value::Array(overlay)) => overlay,
+                    _ => continue,
+                };
+
+                let first = !listed.contains(&section);
+                if first {
+                    listed.push(section);
+                }
+
+                // An empty list, or `None`, disables the section.
+                if first || overlay.is_empty() {
+                    config[section] = Value::Array(vec![]);
+                }
+
+                if let Value::Array(instances) = &mut config[section] {
+                    merge_instances(instances, overlay, &defaults[section][0]);
+                }
+            }
+
+            merge(&mut config, layer);
+        }
+
+        Ok(serde_path_to_error::deserialize(config)?)
+    }
+
+    /// The config in RON, with the struct names kept so it reads like the config.ron in the
+    /// repository.
+    pub fn to_ron(&self) -> anyhow::Result<String> {
+        let pretty = PrettyConfig::new().struct_names(true);
+
+        Ok(ron::ser::to_string_pretty(self, pretty)? + "\n")
+    }
+}
+
+/// Prefixes the host of the url, or the url itself when it is a bare host, when its first DNS
+/// label is one of the names.
+fn prefix_host(url: &str, names: &[String], prefix: &str) -> String {
+    let host_start = url.find("://").map_or(0, |i| i + 3);
+    let label_end = url[host_start..]
+        .find(['.', ':', '/'])
+        .map_or(url.len(), |i| host_start + i);
+
+    if names.iter().any(|x| *x == url[host_start..label_end]) {
+        format!("{}{}{}", &url[..host_start], prefix, &url[host_start..])
+    } else {
+        url.to_string()
+    }
+}
+
+impl Config {
+    /// The name of the instance with the prefix of the stack.
+    pub fn prefixed(&self, name: &str) -> String {
+        match self.stack.as_ref().and_then(|x| x.prefix.as_ref()) {
+            Some(prefix) => prefix.to_string() + name,
+            None => name.to_string(),
+        }
+    }
+
+    /// Prepends the prefix of the stack to the name of every instance, and to the urls and hosts
+    /// pointing to the services of other instances.
+    pub fn apply_stack_prefix(&mut self) {
+        let prefix = match self.stack.as_ref().and_then(|x| x.prefix.clone()) {
+            Some(prefix) => prefix,
+            None => return,
+        };
+
+        let names: Vec<String> = [
+            self.api.iter().map(|x| x.name()).collect::<Vec<_>>(),
+            self.explorer.iter().map(|x| x.name()).collect(),
+            self.ipfs.iter().map(|x| x.name()).collect(),
+            self.keycloak.iter().map(|x| x.name()).collect(),
+            self.node.iter().map(|x| x.name()).collect(),
+            self.status.iter().map(|x| x.name()).collect(),
+        ]
+        .concat()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+
+        for api in &mut self.api {
+            api.name = prefix.to_string() + &api.name;
+            api.node_url = prefix_host(&api.node_url, &names, &prefix);
+        }
+
+        for explorer in &mut self.explorer {
+            explorer.name = prefix.to_string() + &explorer.name;
+            explorer.ws_url = prefix_host(&explorer.ws_url, &names, &prefix);
+        }
+
+        for ipfs in &mut self.ipfs {
+            ipfs.name = prefix.to_string() + &ipfs.name;
+        }
+
+        for keycloak in &mut self.keycloak {
+            keycloak.name = prefix.to_string() + &keycloak.name;
+            keycloak.db_config.db_address =
+                prefix_host(&keycloak.db_config.db_address, &names, &prefix);
+        }
+
+        for node in &mut self.node {
+            node.name = prefix.to_string() + &node.name;
+
+            if let Some(bootnode) = &mut node.bootnode {
+                if let Some(dns_url) = &bootnode.dns_url {
+                    bootnode.dns_url = Some(prefix_host(dns_url, &names, &prefix));
+                }
+            }
+        }
+
+        for status in &mut self.status {
+            status.name = prefix.to_string() + &status.name;
+            status.node_url = prefix_host(&status.node_url, &names, &prefix);
+        }
+
+        if let Some(registry) = &mut self.registry {
+            registry.name = prefix.to_string() + &registry.name;
+        }
+
+        // The TLS secret is written by cert-manager for the ingress, so it is named per stack too.
+        if let Some(ingress) = &mut self.ingress {
+            ingress.name = prefix.to_string() + &ingress.name;
+            ingress.tls_secret = prefix.to_string() + &ingress.tls_secret;
+        }
+    }
+}
+
+/// Component sections with several instances, with the plural alias of each one.
+const INSTANCE_SECTIONS: [(&str, &str); 6] = [
+    ("api", "apis"),
+    ("explorer", "explorers"),
+    ("ipfs", "ipfs"),
+    ("keycloak", "keycloaks"),
+    ("node", "nodes"),
+    ("status", "statuses"),
+];
+
+/// Optional sections holding a single struct.
+const OPTIONAL_SECTIONS: [&str; 3] = ["stack", "registry", "ingress"];
+
+/// Node names of the development accounts, whose flags insert the keys of the account.
+const DEV_ACCOUNTS: [&str; 6] = ["alice", "bob", "charlie", "dave", "eve", "ferdie"];
+
+/// Rewrites the component sections of a layer as lists under their singular name, so the
+/// single instance forms and the plural aliases merge like the lists of the defaults. A section
+/// set to `false` is disabled like with `None`, which TOML cannot write.
+fn normalize_sections(mut layer: Value) -> anyhow::Result<Value> {
+    if let Value::Object(fields) = &mut layer {
+        for section in OPTIONAL_SECTIONS {
+            if let Some(value @ Value::Bool(false)) = fields.get_mut(section) {
+                *value = Value::Null;
+            }
+        }
+
+        for (section, alias) in INSTANCE_SECTIONS {
+            let mut value = fields.remove(section);
+
+            if let Some(aliased) = fields.remove(alias) {
+                if value.is_some() {
+                    anyhow::bail!("duplicate field `{}`, also given as `{}`", section, alias);
+                }
+                value = Some(aliased);
+            }
+
+            let instances = match value {
+                Some(Value::Array(instances)) => instances,
+                Some(Value::Null | Value::Bool(false)) => vec![],
+                Some(instance) => vec![instance],
+                None => continue,
+            };
+
+            fields.insert(section.to_string(), Value::Array(instances));
+        }
+    }
+
+    Ok(layer)
+}
+
+/// Overlay instances are merged into the base instance with the same name, or into the one at
+/// the same position when they have no name. Other instances are added on top of the defaults
+/// of the section.
+fn merge_instances(base: &mut Vec<Value>, overlay: Vec<Value>, default: &Value) {
+    for (i, instance) in overlay.into_iter().enumerate() {
+        let name = instance.get("name").cloned();
+        let position = match &name {
+            Some(name) => base.iter().position(|x| x.get("name") == Some(name)),
+            None if i < base.len() => Some(i),
+            None => None,
+        };
+
+        match position {
+            Some(position) => merge(&mut base[position], instance),
+            None => {
+                let mut new_instance = default.clone();
+                merge(&mut new_instance, instance);
+                base.push(new_instance);
+            }
+        }
+    }
+}
+
+/// Merges the overlay into the base field by field. Values other than structs replace the base
+/// value, so `None` in an overlay unsets an optional field.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Resolves an `env:NAME` or `file:/path` reference to the value of the environment variable or
+/// the contents of the file. Other values are returned as they are.
+fn resolve(path: &str, value: &str) -> Result<String, ConfigProblem> {
+    if let Some(name) = value.strip_prefix("env:") {
+        return env::var(name).map_err(|_| ConfigProblem {
+            path: path.to_string(),
+            message: format!("environment variable {} is not set", name),
+        });
+    }
+
+    if let Some(file) = value.strip_prefix("file:") {
+        return match fs::read_to_string(file) {
+            // Files written by editors and secret stores usually end with a newline.
+            Ok(contents) => Ok(contents.trim_end_matches(&['\r', '\n'][..]).to_string()),
+            Err(e) => Err(ConfigProblem {
+                path: path.to_string(),
+                message: format!("cannot read file {}: {}", file, e),
+            }),
+        };
+    }
+
+    Ok(value.to_string())
+}
+
+fn resolve_field(problems: &mut Vec<ConfigProblem>, path: &str, value: &mut String) {
+    match resolve(path, value) {
+        Ok(resolved) => *value = resolved,
+        Err(problem) => problems.push(problem),
+    }
+}
+
+impl Config {
+    /// Replaces the `env:` and `file:` references in the secret values with what they point to,
+    /// returns the references that could not be resolved.
+    pub fn resolve_references(&mut self) -> Vec<ConfigProblem> {
+        let mut problems: Vec<ConfigProblem> = vec![];
+
+        if let Some(registry) = &mut self.registry {
+            resolve_field(&mut problems, "registry.password", &mut registry.password);
+        }
+
+        for (i, ipfs) in self.ipfs.iter_mut().enumerate() {
+            if let Some(swarm_key) = &mut ipfs.swarm_key {
+                let path = format!("ipfs[{}].swarm_key", i);
+                resolve_field(&mut problems, &path, swarm_key);
+            }
+        }
+
+        for (i, node) in self.node.iter_mut().enumerate() {
+            if let Some(node_key) = &mut node.node_key {
+                let path = format!("node[{}].node_key", i);
+                resolve_field(&mut problems, &path, node_key);
+            }
+
+            if let Some(session_keys) = &mut node.session_keys {
+                let path = format!("node[{}].session_keys.aura", i);
+                resolve_field(&mut problems, &path, &mut session_keys.aura);
+
+                let path = format!("node[{}].session_keys.grandpa", i);
+                resolve_field(&mut problems, &path, &mut session_keys.grandpa);
+            }
+        }
+
+        for (i, keycloak) in self.keycloak.iter_mut().enumerate() {
+            let path = format!("keycloak[{}].admin_password", i);
+            resolve_field(&mut problems, &path, &mut keycloak.admin_password);
+
+            let path = format!("keycloak[{}].db_config.db_password", i);
+            resolve_field(&mut problems, &path, &mut keycloak.db_config.db_password);
+        }
+
+        problems
+    }
+}
+
+/// A semantic problem in the config, with the path of the offending field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Kubernetes requires service names to be DNS-1035 labels.
+fn is_dns_label(name: &str) -> bool {
+    let bytes = name.as_bytes();
+
+    !bytes.is_empty()
+        && bytes.len() <= 63
+        && bytes[0].is_ascii_lowercase()
+        && bytes[bytes.len() - 1] != b'-'
+        && bytes
+            .iter()
+            .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || *x == b'-')
+}
+
+/// Libp2p peer ids are base58 multihashes of the public key: identity for ed25519 keys and
+/// sha2-256 for RSA keys.
+fn is_peer_id(value: &str) -> bool {
+    match bs58::decode(value).into_vec() {
+        Ok(bytes) => match bytes.as_slice() {
+            [0x00, len, key @ ..] => *len as usize == key.len(),
+            [0x12, 0x20, hash @ ..] => hash.len() == 32,
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+fn check_name(problems: &mut Vec<ConfigProblem>, path: &str, name: &str) {
+    if !is_dns_label(name) {
+        problems.push(ConfigProblem {
+            path: path.to_string() + ".name",
+            message: format!(
+                "{:?} must be a DNS-1035 label: lowercase alphanumerics or '-', starting with a letter and at most 63 characters",
+                name
+            ),
+        });
+    }
+}
+
+fn check_ports(problems: &mut Vec<ConfigProblem>, path: &str, ports: &[(&str, i32)]) {
+    for (i, (field, port)) in ports.iter().enumerate() {
+        if !(1..=65535).contains(port) {
+            problems.push(ConfigProblem {
+                path: format!("{}.{}", path, field),
+                message: format!("{} is not in the range 1-65535", port),
+            });
+        }
+
+        if let Some((other_field, _)) = ports[..i].iter().find(|(_, x)| x == port) {
+            problems.push(ConfigProblem {
+                path: format!("{}.{}", path, field),
+                message: format!("{} is already used by {}.{}", port, path, other_field),
+            });
+        }
+    }
+}
+
+/// Whether the value is a Kubernetes quantity, like `500m`, `1.5`, `128Mi` or `1e3`.
+fn is_quantity(value: &str) -> bool {
+    let number_end = value
+        .char_indices()
+        .find(|(i, x)| !(x.is_ascii_digit() || *x == '.' || (*i == 0 && (*x == '+' || *x == '-'))))
+        .map_or(value.len(), |(i, _)| i);
+    let (number, suffix) = value.split_at(number_end);
+    let digits = number.trim_start_matches(&['+', '-'][..]);
+
+    if digits.is_empty() || digits == "." || digits.matches('.').count() > 1 {
+        return false;
+    }
+
+    let suffixes = [
+        "", "m", "k", "M", "G", "T", "P", "E", "Ki", "Mi", "Gi", "Ti", "Pi", "Ei",
+    ];
+
+    suffixes.contains(&suffix)
+        || suffix
+            .strip_prefix(&['e', 'E'][..])
+            .map(|x| x.strip_prefix(&['+', '-'][..]).unwrap_or(x))
+            .is_some_and(|x| !x.is_empty() && x.chars().all(|x| x.is_ascii_digit()))
+}
+
+fn check_resources(
+    problems: &mut Vec<ConfigProblem>,
+    path: &str,
+    resources: &Option<ResourcesConfig>,
+) {
+    let resources = match resources {
+        Some(resources) => resources,
+        None => return,
+    };
+
+    for (kind, quantities) in [
+        ("requests", &resources.requests),
+        ("limits", &resources.limits),
+    ] {
+        let quantities = match quantities {
+            Some(quantities) => quantities,
+            None => continue,
+        };
+
+        for (field, quantity) in [
+            ("cpu", &quantities.cpu),
+            ("memory", &quantities.memory),
+            ("ephemeral_storage", &quantities.ephemeral_storage),
+        ] {
+            if let Some(quantity) = quantity {
+                if !is_quantity(quantity) {
+                    problems.push(ConfigProblem {
+                        path: format!("{}.pod.resources.{}.{}", path, kind, field),
+                        message: format!("{:?} is not a quantity", quantity),
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Checks the config for the mistakes the API server would only report halfway through a
+    /// deploy. The chainspec secret of the testnet node lives in the cluster, so it is checked
+    /// by the caller.
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems: Vec<ConfigProblem> = vec![];
+        // Every object of an instance is named after it, so names are unique across components.
+        let mut names: Vec<(String, String)> = vec![];
+        let mut check_unique = |problems: &mut Vec<ConfigProblem>, path: &str, name: &str| {
+            if let Some((other_path, _)) = names.iter().find(|(_, x)| x == name) {
+                problems.push(ConfigProblem {
+                    path: path.to_string() + ".name",
+                    message: format!("{:?} is already used by {}", name, other_path),
+                });
+            }
+            names.push((path.to_string(), name.to_string()));
+        };
+
+        if let Some(registry) = &self.registry {
+            check_name(&mut problems, "registry", &registry.name);
+            check_unique(&mut problems, "registry", &registry.name);
+
+            for (field, value) in [
+                ("server", &registry.server),
+                ("username", &registry.username),
+                ("password", &registry.password),
+            ] {
+                if value.is_empty() {
+                    problems.push(ConfigProblem {
+                        path: format!("registry.{}", field),
+                        message: "must not be empty".to_string(),
+                    });
+                }
+            }
+        }
+
+        for (i, api) in self.api.iter().enumerate() {
+            let path = format!("api[{}]", i);
+            check_name(&mut problems, &path, &api.name);
+            check_unique(&mut problems, &path, &api.name);
+            check_resources(&mut problems, &path, &api.pod.resources);
+            check_ports(&mut problems, &path, &[("port", api.port)]);
+        }
+
+        for (i, explorer) in self.explorer.iter().enumerate() {
+            let path = format!("explorer[{}]", i);
+            check_name(&mut problems, &path, &explorer.name);
+            check_unique(&mut problems, &path, &explorer.name);
+            check_resources(&mut problems, &path, &explorer.pod.resources);
+            check_ports(&mut problems, &path, &[("port", explorer.port)]);
+        }
+
+        for (i, ipfs) in self.ipfs.iter().enumerate() {
+            let path = format!("ipfs[{}]", i);
+            check_name(&mut problems, &path, &ipfs.name);
+            check_unique(&mut problems, &path, &ipfs.name);
+            check_resources(&mut problems, &path, &ipfs.pod.resources);
+            check_ports(
+                &mut problems,
+                &path,
+                &[
+                    ("swarm_tcp_port", ipfs.swarm_tcp_port),
+                    ("swarm_udp_port", ipfs.swarm_udp_port),
+                    ("api_port", ipfs.api_port),
+                ],
+            );
+
+            if let Some(swarm_key) = &ipfs.swarm_key {
+                if swarm_key.len() != 64 || !swarm_key.chars().all(|x| x.is_ascii_hexdigit()) {
+                    problems.push(ConfigProblem {
+                        path: path.to_string() + ".swarm_key",
+                        message: "must be 64 hexadecimal characters".to_string(),
+                    });
+                }
+            }
+        }
+
+        for (i, keycloak) in self.keycloak.iter().enumerate() {
+            let path = format!("keycloak[{}]", i);
+            check_name(&mut problems, &path, &keycloak.name);
+            check_unique(&mut problems, &path, &keycloak.name);
+            check_resources(&mut problems, &path, &keycloak.pod.resources);
+            check_ports(&mut problems, &path, &[("port", keycloak.port)]);
+            check_ports(
+                &mut problems,
+                &(path.to_string() + ".db_config"),
+                &[("db_port", keycloak.db_config.db_port)],
+            );
+        }
+
+        for (i, node) in self.node.iter().enumerate() {
+            let path = format!("node[{}]", i);
+            check_name(&mut problems, &path, &node.name);
+            check_unique(&mut problems, &path, &node.name);
+            check_resources(&mut problems, &path, &node.pod.resources);
+            check_ports(
+                &mut problems,
+                &path,
+                &[
+                    ("ws_port", node.ws_port),
+                    ("rpc_port", node.rpc_port),
+                    ("p2p_port", node.p2p_port),
+                    ("prometheus_port", node.prometheus_port),
+                ],
+            );
+
+            if let Some(storage) = &node.storage {
+                let storage_path = path.to_string() + ".storage";
+
+                if !is_quantity(&storage.size) {
+                    problems.push(ConfigProblem {
+                        path: storage_path.to_string() + ".size",
+                        message: format!("{:?} is not a quantity", storage.size),
+                    });
+                }
+
+                if !storage.mount_path.starts_with('/') {
+                    problems.push(ConfigProblem {
+                        path: storage_path + ".mount_path",
+                        message: format!("{:?} is not an absolute path", storage.mount_path),
+                    });
+                }
+            }
+
+            if let Some(node_key) = &node.node_key {
+                let node_key_path = path.to_string() + ".node_key";
+
+                if node_key.len() != 64 || !node_key.chars().all(|x| x.is_ascii_hexdigit()) {
+                    problems.push(ConfigProblem {
+                        path: node_key_path,
+                        message: "must be 64 hexadecimal characters".to_string(),
+                    });
+                } else if let Some(other) = self.node[..i]
+                    .iter()
+                    .position(|x| x.node_key.as_ref() == Some(node_key))
+                {
+                    problems.push(ConfigProblem {
+                        path: node_key_path,
+                        message: format!("is already used by node[{}]", other),
+                    });
+                }
+            }
+
+            if let Some(p2p_service) = &node.p2p_service {
+                let p2p_service_path = path.to_string() + ".p2p_service";
+
+                if let Some(node_port) = p2p_service.node_port {
+                    if !(30000..=32767).contains(&node_port) {
+                        problems.push(ConfigProblem {
+                            path: p2p_service_path.to_string() + ".node_port",
+                            message: format!("{} is not in the range 30000-32767", node_port),
+                        });
+                    }
+                }
+
+                // The announced address needs the port, only known in advance when it is set.
+                if p2p_service.service_type == P2pServiceType::NodePort
+                    && p2p_service.public_host.is_some()
+                    && p2p_service.node_port.is_none()
+                {
+                    problems.push(ConfigProblem {
+                        path: p2p_service_path + ".node_port",
+                        message: "is required to announce the public_host".to_string(),
+                    });
+                }
+            }
+
+            if let Some(session_keys) = &node.session_keys {
+                let session_keys_path = path.to_string() + ".session_keys";
+
+                for (field, value) in [
+                    ("aura", &session_keys.aura),
+                    ("grandpa", &session_keys.grandpa),
+                ] {
+                    if value.is_empty() {
+                        problems.push(ConfigProblem {
+                            path: format!("{}.{}", session_keys_path, field),
+                            message: "must not be empty".to_string(),
+                        });
+                    }
+                }
+
+                // The node of a development account also gets the keys of the account.
+                if DEV_ACCOUNTS.contains(&node.node_name.to_lowercase().as_str()) {
+                    problems.push(ConfigProblem {
+                        path: path.to_string() + ".node_name",
+                        message: format!(
+                            "{} is a development account, which cannot have session_keys",
+                            node.node_name
+                        ),
+                    });
+                }
+
+                // The keys are inserted into the keystore by an init container.
+                if node.storage.is_none() {
+                    problems.push(ConfigProblem {
+                        path: session_keys_path,
+                        message: "requires the storage of the node".to_string(),
+                    });
+                }
+            }
+
+            if let Some(chainspec_ext) = &node.chainspec_ext {
+                if chainspec_ext.chainspec_url.is_empty() {
+                    problems.push(ConfigProblem {
+                        path: path.to_string() + ".chainspec_ext.chainspec_url",
+                        message: "must not be empty".to_string(),
+                    });
+                }
+            }
+
+            if let Some(bootnode) = &node.bootnode {
+                let bootnode_path = path.to_string() + ".bootnode";
+                check_ports(
+                    &mut problems,
+                    &bootnode_path,
+                    &[("p2p_port", bootnode.p2p_port)],
+                );
+
+                if !is_peer_id(&bootnode.private_key) {
+                    problems.push(ConfigProblem {
+                        path: bootnode_path + ".private_key",
+                        message: format!("{:?} is not a libp2p peer id", bootnode.private_key),
+                    });
+                }
+            }
+        }
+
+        for (i, status) in self.status.iter().enumerate() {
+            let path = format!("status[{}]", i);
+            check_name(&mut problems, &path, &status.name);
+            check_unique(&mut problems, &path, &status.name);
+            check_resources(&mut problems, &path, &status.pod.resources);
+            check_ports(&mut problems, &path, &[("port", status.port)]);
+        }
+
+        if let Some(ingress) = &self.ingress {
+            check_name(&mut problems, "ingress", &ingress.name);
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn names<T: Instance>(instances: &[T]) -> Vec<&str> {
+        instances.iter().map(|x| x.name()).collect()
+    }
+
+    fn load(format: ConfigFormat, contents: &str) -> anyhow::Result<Config> {
+        Config::from_layers(vec![format.parse(contents)?])
+    }
+
+    #[test]
+    fn default_configs_are_valid() {
+        for chain in [SugarfungeChainType::Local, SugarfungeChainType::Testnet] {
+            assert_eq!(Config::for_chain(chain).validate(), vec![]);
+        }
+    }
+
+    #[test]
+    fn dns_labels() {
+        for name in ["a", "sf-node", "sf-node-1", &"a".repeat(63)] {
+            assert!(is_dns_label(name), "{}", name);
+        }
+
+        for name in [
+            "",
+            "1-node",
+            "-node",
+            "node-",
+            "sf_node",
+            "Node",
+            &"a".repeat(64),
+        ] {
+            assert!(!is_dns_label(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn quantities() {
+        for value in [
+            "1", "+1", "-1", "1.5", ".5", "500m", "128Mi", "2Gi", "1e3", "1E-3", "1k",
+        ] {
+            assert!(is_quantity(value), "{}", value);
+        }
+
+        for value in [
+            "", ".", "m", "1.2.3", "1mi", "1KB", "1e", "1e+", "1 Gi", "Gi1", "--1",
+        ] {
+            assert!(!is_quantity(value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn resources_are_quantities() {
+        let config = load(
+            ConfigFormat::Ron,
+            r#"(api: (pod: (resources: Some((requests: Some((cpu: Some("250m"), memory: Some("1GB"))))))))"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.validate(),
+            vec![ConfigProblem {
+                path: "api[0].pod.resources.requests.memory".to_string(),
+                message: "\"1GB\" is not a quantity".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn peer_ids() {
+        for value in [
+            "12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp",
+            "QmYyQSo1c1Ym7orWxLYvCrM2EmxFTANf8wXmmE7DWjhx5N",
+        ] {
+            assert!(is_peer_id(value), "{}", value);
+        }
+
+        for value in [
+            "",
+            "12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDE",
+            "QmYyQSo1c1Ym7orWxLYvCrM2EmxFTANf8wXmmE7DWjhx5",
+            "12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDE0",
+        ] {
+            assert!(!is_peer_id(value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn ports_are_in_range_and_unique() {
+        let mut problems = vec![];
+        check_ports(
+            &mut problems,
+            "node",
+            &[
+                ("ws_port", 9944),
+                ("p2p_port", 0),
+                ("prometheus_port", 9944),
+            ],
+        );
+
+        assert_eq!(
+            problems,
+            vec![
+                ConfigProblem {
+                    path: "node.p2p_port".to_string(),
+                    message: "0 is not in the range 1-65535".to_string(),
+                },
+                ConfigProblem {
+                    path: "node.prometheus_port".to_string(),
+                    message: "9944 is already used by node.ws_port".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_sections_lists_the_instances() {
+        let layer = normalize_sections(json!({
+            "apis": {"name": "sf-api-1"},
+            "node": [{"name": "sf-node-1"}],
+            "status": false,
+            "keycloak": null,
+            "ingress": false,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            layer,
+            json!({
+                "api": [{"name": "sf-api-1"}],
+                "node": [{"name": "sf-node-1"}],
+                "status": [],
+                "keycloak": [],
+                "ingress": null,
+            })
+        );
+
+        let error = normalize_sections(json!({"api": [], "apis": []})).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "duplicate field `api`, also given as `apis`"
+        );
+    }
+
+    #[test]
+    fn first_listing_layer_replaces_the_default_instances() {
+        let config = Config::from_layers(vec![
+            json!({"api": [{"name": "sf-api-1"}, {"name": "sf-api-2", "port": 4001}]}),
+            json!({"api": [{"name": "sf-api-2", "listen_url": "http://0.0.0.0:4001"}]}),
+            json!({"api": [{"name": "sf-api-3"}]}),
+        ])
+        .unwrap();
+
+        assert_eq!(names(&config.api), ["sf-api-1", "sf-api-2", "sf-api-3"]);
+
+        // Instances added by a layer start from the defaults of the section.
+        assert_eq!(config.api[0].image, ApiConfig::default().image);
+        assert_eq!(config.api[1].port, 4001);
+        assert_eq!(config.api[1].listen_url, "http://0.0.0.0:4001");
+
+        // The sections a layer leaves out keep their defaults.
+        assert_eq!(names(&config.node), ["sf-node"]);
+    }
+
+    #[test]
+    fn unnamed_instances_merge_by_position() {
+        let config = Config::from_layers(vec![
+            json!({"node": [{"name": "sf-node-1"}, {"name": "sf-node-2"}]}),
+            json!({"node": [{}, {"p2p_port": 30335}, {"name": "sf-node-3"}]}),
+            json!({"nodes": {"ws_port": 9945}}),
+        ])
+        .unwrap();
+
+        assert_eq!(names(&config.node), ["sf-node-1", "sf-node-2", "sf-node-3"]);
+        assert_eq!(config.node[0].ws_port, 9945);
+        assert_eq!(config.node[1].p2p_port, 30335);
+        assert_eq!(config.node[1].ws_port, 9944);
+
+        // An unnamed instance in the first listing layer is a default instance.
+        let config = Config::from_layers(vec![json!({"node": {"ws_port": 9945}})]).unwrap();
+
+        assert_eq!(names(&config.node), ["sf-node"]);
+        assert_eq!(config.node[0].ws_port, 9945);
+    }
+
+    #[test]
+    fn false_none_and_empty_lists_disable_a_component() {
+        let config = Config::from_layers(vec![json!({
+            "api": false,
+            "explorer": null,
+            "ipfs": [],
+            "ingress": false,
+        })])
+        .unwrap();
+
+        assert!(config.api.is_empty());
+        assert!(config.explorer.is_empty());
+        assert!(config.ipfs.is_empty());
+        assert!(config.ingress.is_none());
+        assert_eq!(names(&config.keycloak), ["sf-keycloak"]);
+
+        // An empty list disables a section listed by a previous layer too.
+        let config = Config::from_layers(vec![
+            json!({"status": [{"name": "sf-status-1"}]}),
+            json!({"status": []}),
+        ])
+        .unwrap();
+
+        assert!(config.status.is_empty());
+
+        // The fields left out of a section off by default take their defaults.
+        let registry = json!({"username": "sugarfunge", "password": "secret"});
+        let config = Config::from_layers(vec![json!({ "registry": registry })]).unwrap();
+        let registry_config = config.registry.unwrap();
+        assert_eq!(registry_config.name, "sf-registry");
+        assert_eq!(registry_config.username, "sugarfunge");
+
+        let config = Config::from_layers(vec![
+            json!({ "registry": registry }),
+            json!({"registry": false}),
+        ])
+        .unwrap();
+        assert!(config.registry.is_none());
+
+        let config = load(ConfigFormat::Ron, "(keycloak: None, stack: None)").unwrap();
+        assert!(config.keycloak.is_empty());
+    }
+
+    #[test]
+    fn merge_replaces_values_other_than_structs() {
+        let mut base = json!({
+            "storage": {"size": "20Gi", "mount_path": "/data"},
+            "extra_args": ["--a"],
+            "rpc": null,
+        });
+
+        merge(
+            &mut base,
+            json!({"storage": {"size": "50Gi"}, "extra_args": ["--b"], "rpc": {"cors": ["all"]}}),
+        );
+        assert_eq!(
+            base,
+            json!({
+                "storage": {"size": "50Gi", "mount_path": "/data"},
+                "extra_args": ["--b"],
+                "rpc": {"cors": ["all"]},
+            })
+        );
+
+        merge(&mut base, json!({"storage": null}));
+        assert_eq!(base["storage"], Value::Null);
+    }
+
+    #[test]
+    fn merge_instances_adds_the_unmatched_ones() {
+        let default = json!({"name": "sf-api", "port": 4000});
+        let mut base = vec![json!({"name": "sf-api-1", "port": 4001})];
+
+        merge_instances(
+            &mut base,
+            vec![json!({"port": 4002}), json!({"name": "sf-api-2"})],
+            &default,
+        );
+
+        assert_eq!(
+            base,
+            vec![
+                json!({"name": "sf-api-1", "port": 4002}),
+                json!({"name": "sf-api-2", "port": 4000}),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let mut config = Config::for_chain(SugarfungeChainType::Testnet);
+        config.api.push(ApiConfig {
+            name: "sf-api-2".to_string(),
+            ..Default::default()
+        });
+        config.ingress = None;
+
+        let layer = serde_json::to_value(&config).unwrap();
+        let loaded = Config::from_layers(vec![layer]).unwrap();
+        assert_eq!(loaded.to_ron().unwrap(), config.to_ron().unwrap());
+    }
+
+    #[test]
+    fn ron_round_trips() {
+        let mut config = Config::for_chain(SugarfungeChainType::Testnet);
+        config.stack = Some(StackConfig {
+            prefix: Some("demo-".to_string()),
+            ..Default::default()
+        });
+        config.node[0].node_key = Some("01".repeat(32));
+        config.node[0].pod.extra_args = Some(vec!["--log=info".to_string()]);
+        config.node[0].p2p_service = Some(P2pServiceConfig {
+            service_type: P2pServiceType::LoadBalancer,
+            ..Default::default()
+        });
+
+        let contents = config.to_ron().unwrap();
+        let loaded = load(ConfigFormat::Ron, &contents).unwrap();
+        assert_eq!(loaded.to_ron().unwrap(), contents);
+    }
+
+    #[test]
+    fn formats_load_the_same_config() {
+        let expected = load(
+            ConfigFormat::Ron,
+            r#"(api: [(name: "sf-api-1", port: 4001)], node: (p2p_port: 30335))"#,
+        )
+        .unwrap()
+        .to_ron()
+        .unwrap();
+
+        for (format, contents) in [
+            (
+                ConfigFormat::Yaml,
+                "api:\n  - name: sf-api-1\n    port: 4001\nnode:\n  p2p_port: 30335\n",
+            ),
+            (
+                ConfigFormat::Toml,
+                "[[api]]\nname = \"sf-api-1\"\nport = 4001\n\n[node]\np2p_port = 30335\n",
+            ),
+            (
+                ConfigFormat::Json,
+                r#"{"api": [{"name": "sf-api-1", "port": 4001}], "node": {"p2p_port": 30335}}"#,
+            ),
+        ] {
+            assert_eq!(load(format, contents).unwrap().to_ron().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn null_yaml_keeps_the_defaults() {
+        let config = load(ConfigFormat::Yaml, "~").unwrap();
+        assert_eq!(
+            config.to_ron().unwrap(),
+            Config::default().to_ron().unwrap()
+        );
+
+        let error = load(ConfigFormat::Yaml, "- sf-api").unwrap_err();
+        assert_eq!(error.to_string(), "the config must be a struct or a map");
+    }
+
+    #[test]
+    fn prefix_host_prefixes_the_instance_names() {
+        let names = ["sf-node".to_string(), "sf-db".to_string()];
+
+        for (url, expected) in [
+            ("ws://sf-node:9944", "ws://demo-sf-node:9944"),
+            (
+                "ws://sf-node.default.svc.cluster.local",
+                "ws://demo-sf-node.default.svc.cluster.local",
+            ),
+            ("http://sf-node/rpc", "http://demo-sf-node/rpc"),
+            ("sf-db", "demo-sf-db"),
+            ("sf-db:5432", "demo-sf-db:5432"),
+            ("wss://node.sugarfunge.dev", "wss://node.sugarfunge.dev"),
+            ("ws://sf-node-1:9944", "ws://sf-node-1:9944"),
+            ("sf-nodes.example.com", "sf-nodes.example.com"),
+        ] {
+            assert_eq!(prefix_host(url, &names, "demo-"), expected);
+        }
+    }
+
+    #[test]
+    fn stack_prefix_renames_the_instances_and_their_references() {
+        let mut config = load(
+            ConfigFormat::Ron,
+            r#"(stack: Some((prefix: Some("demo-"))), explorer: (ws_url: "wss://node.sugarfunge.dev"))"#,
+        )
+        .unwrap();
+        config.apply_stack_prefix();
+
+        assert_eq!(names(&config.api), ["demo-sf-api"]);
+        assert_eq!(config.api[0].node_url, "ws://demo-sf-node:9944");
+        assert_eq!(config.explorer[0].ws_url, "wss://node.sugarfunge.dev");
+        assert_eq!(config.keycloak[0].db_config.db_address, "sf-db-postgresql");
+        assert_eq!(config.prefixed("sf-node"), "demo-sf-node");
+
+        let ingress = config.ingress.unwrap();
+        assert_eq!(ingress.name, "demo-sf-ingress");
+        assert_eq!(
+            ingress.tls_secret,
+            "demo-".to_string() + &IngressConfig::default().tls_secret
+        );
+    }
+
+    #[test]
+    fn omitted_fields_take_their_defaults() {
+        let config = load(
+            ConfigFormat::Toml,
+            "[node]\nws_port = 9945\n\n[node.bootnode]\np2p_port = 30335\n",
+        )
+        .unwrap();
+
+        let node = &config.node[0];
+        assert_eq!(node.ws_port, 9945);
+        assert_eq!(node.p2p_port, NodeConfig::default().p2p_port);
+
+        let bootnode = node.bootnode.as_ref().unwrap();
+        assert_eq!(bootnode.p2p_port, 30335);
+        assert_eq!(bootnode.private_key, BootNode::default().private_key);
+    }
+
+    #[test]
+    fn node_keys_are_hex_and_unique() {
+        let node_key = "01".repeat(32);
+        let config = Config::from_layers(vec![json!({"node": [
+            {"name": "sf-node-1", "node_key": node_key},
+            {"name": "sf-node-2", "node_key": node_key},
+            {"name": "sf-node-3", "node_key": "01"},
+        ]})])
+        .unwrap();
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                ConfigProblem {
+                    path: "node[1].node_key".to_string(),
+                    message: "is already used by node[0]".to_string(),
+                },
+                ConfigProblem {
+                    path: "node[2].node_key".to_string(),
+                    message: "must be 64 hexadecimal characters".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ron_keeps_unit_values() {
+        let config = load(
+            ConfigFormat::Ron,
+            r#"(node: [(pod: (extra_volumes: Some([(name: "logs", emptyDir: Some(()))])))])"#,
+        )
+        .unwrap();
+
+        let volume = &config.node[0].pod.extra_volumes.as_ref().unwrap()[0];
+        assert_eq!(volume.name, "logs");
+        assert!(volume.empty_dir.is_some());
+    }
+
+    #[test]
+    fn ron_skips_strings_and_comments() {
+        let config = load(
+            ConfigFormat::Ron,
+            r##"
+            // Unsafe
+            (
+                /* outer /* Unsafe */ */
+                api: [(name: "Unsafe", image: r#"a "Safe" b"#)],
+            )
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.api[0].name, "Unsafe");
+        assert_eq!(config.api[0].image, r#"a "Safe" b"#);
+    }
+
+    #[test]
+    fn config_init_loads_back() {
+        for (chain, methods) in [
+            (SugarfungeChainType::Local, RpcMethods::Unsafe),
+            (SugarfungeChainType::Testnet, RpcMethods::Safe),
+        ] {
+            let contents = Config::for_chain(chain).to_ron().unwrap();
+            let config = load(ConfigFormat::Ron, &contents).unwrap();
+
+            let rpc = config.node[0].rpc.as_ref().unwrap();
+            assert_eq!(rpc.methods, methods);
+        }
+    }
+
+    #[test]
+    fn rpc_loads_from_ron() {
+        let config = load(
+            ConfigFormat::Ron,
+            r#"(node: [(rpc: Some(RpcConfig(methods: Auto, cors: Some(["all"]))))])"#,
+        )
+        .unwrap();
+
+        let rpc = config.node[0].rpc.as_ref().unwrap();
+        assert_eq!(rpc.methods, RpcMethods::Auto);
+        assert_eq!(rpc.cors, Some(vec!["all".to_string()]));
+    }
+
+    #[test]
+    fn p2p_service_loads_from_ron() {
+        // The example of the README
+        let config = load(
+            ConfigFormat::Ron,
+            r#"(node: [(p2p_service: Some(P2pServiceConfig(service_type: NodePort, node_port: Some(30334), public_host: Some("node.demo.sugarfunge.dev"))))])"#,
+        )
+        .unwrap();
+
+        let p2p_service = config.node[0].p2p_service.as_ref().unwrap();
+        assert_eq!(p2p_service.service_type, P2pServiceType::NodePort);
+        assert_eq!(p2p_service.node_port, Some(30334));
+
+        let config = load(
+            ConfigFormat::Ron,
+            "(node: [(p2p_service: Some((service_type: LoadBalancer)))])",
+        )
+        .unwrap();
+
+        let p2p_service = config.node[0].p2p_service.as_ref().unwrap();
+        assert_eq!(p2p_service.service_type, P2pServiceType::LoadBalancer);
+    }
+
+    #[test]
+    fn session_keys_need_a_node_name() {
+        let mut config = load(
+            ConfigFormat::Ron,
+            r#"(node: [(node_name: "Alice", session_keys: Some((aura: "//a", grandpa: "//b")))])"#,
+        )
+        .unwrap();
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].path, "node[0].node_name");
+
+        config.node[0].node_name = "validator-1".to_string();
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn errors_have_the_field_path() {
+        for (format, contents) in [
+            (ConfigFormat::Ron, r#"(api: [(port: "x")])"#),
+            (ConfigFormat::Yaml, "api:\n  - port: x\n"),
+            (ConfigFormat::Json, r#"{"api": [{"port": "x"}]}"#),
+        ] {
+            let error = load(format, contents).unwrap_err().to_string();
+            assert!(error.starts_with("api[0].port: invalid type"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn pod_errors_have_the_field_path() {
+        let error = load(ConfigFormat::Ron, "(node: [(pod: (extra_args: Some(3)))])")
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            error.starts_with("node[0].pod.extra_args: invalid type"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let error = load(ConfigFormat::Yaml, "node: {prometheus_prot: 1}")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("node[0].prometheus_prot: unknown field"),
+            "{}",
+            error
+        );
+
+        let error = load(ConfigFormat::Ron, "(stack: Some((prefx: Some(\"a-\"))))")
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("stack.prefx: unknown field"), "{}", error);
+    }
+
+    #[test]
+    fn readme_pod_examples_load() {
+        let config = load(
+            ConfigFormat::Yaml,
+            "node:
+  pod:
+    scheduling:
+      node_selector: {role: validator}
+      tolerations:
+        - {key: dedicated, operator: Equal, value: validator, effect: NoSchedule}
+      topology_spread:
+        - {maxSkew: 1, topologyKey: topology.kubernetes.io/zone, whenUnsatisfiable: ScheduleAnyway}
+    extra_args: [\"--log\", \"runtime=debug\"]
+    extra_env: [{name: RUST_BACKTRACE, value: \"1\"}]
+    sidecars: [{name: exporter, image: \"prom/node-exporter\"}]
+",
+        )
+        .unwrap();
+        let pod = &config.node[0].pod;
+        assert!(pod.scheduling.is_some());
+        assert_eq!(pod.sidecars.as_ref().unwrap()[0].name, "exporter");
+
+        let config = load(
+            ConfigFormat::Ron,
+            r#"(api: (pod: PodConfig(
+    resources: Some(ResourcesConfig(
+        requests: Some(ResourceQuantities(cpu: Some("500m"), memory: Some("1Gi"), ephemeral_storage: None)),
+        limits: Some(ResourceQuantities(cpu: None, memory: Some("2Gi"), ephemeral_storage: Some("10Gi"))),
+    )),
+)))"#,
+        )
+        .unwrap();
+        assert!(config.api[0].pod.resources.is_some());
+    }
+
+    #[test]
+    fn ron_syntax_errors_have_the_position() {
+        let error = load(ConfigFormat::Ron, "(\n  api: [(port: 1,)\n")
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.ends_with("at line 3 column 1"), "{}", error);
+    }
+}
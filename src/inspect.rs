@@ -0,0 +1,627 @@
+use std::{fmt::Debug, time::Duration};
+
+use k8s_openapi::api::{
+    apps::v1::{Deployment, StatefulSet},
+    core::v1::{ContainerStatus, Pod, PodSpec, Secret, Service},
+    networking::v1::Ingress,
+};
+use kube::{
+    api::ListParams,
+    runtime::wait::{await_condition, Condition},
+    Api, Client, Resource,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use similar::TextDiff;
+
+use crate::{
+    config::{Config, StackConfig},
+    resources::{self, stack_labels, Manifest},
+    utils::{get_resource, list_managed_resources, K8sObject, K8sResource},
+    SugarfungeResource,
+};
+
+fn images(pod_spec: Option<&PodSpec>) -> String {
+    let images: Vec<String> = pod_spec
+        .map(|spec| {
+            spec.containers
+                .iter()
+                .filter_map(|container| container.image.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    images.join(", ")
+}
+
+fn workload_status(kind: &str, ready: i32, desired: i32, images: String) -> bool {
+    println!("  {}: {}/{} ready, image {}", kind, ready, desired, images);
+    ready >= desired
+}
+
+async fn deployment_status(client: Client, namespace: &str, name: &str) -> anyhow::Result<bool> {
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+
+    match deployments.get_opt(name).await? {
+        Some(deployment) => {
+            let spec = deployment.spec.unwrap_or_default();
+            let status = deployment.status.unwrap_or_default();
+
+            Ok(workload_status(
+                "Deployment",
+                status.ready_replicas.unwrap_or(0),
+                spec.replicas.unwrap_or(1),
+                images(spec.template.spec.as_ref()),
+            ))
+        }
+        None => {
+            println!("  Deployment: not found");
+            Ok(false)
+        }
+    }
+}
+
+async fn statefulset_status(client: Client, namespace: &str, name: &str) -> anyhow::Result<bool> {
+    let statefulsets: Api<StatefulSet> = Api::namespaced(client, namespace);
+
+    match statefulsets.get_opt(name).await? {
+        Some(statefulset) => {
+            let spec = statefulset.spec.unwrap_or_default();
+            let status = statefulset.status.unwrap_or_default();
+
+            Ok(workload_status(
+                "StatefulSet",
+                status.ready_replicas.unwrap_or(0),
+                spec.replicas.unwrap_or(1),
+                images(spec.template.spec.as_ref()),
+            ))
+        }
+        None => {
+            println!("  StatefulSet: not found");
+            Ok(false)
+        }
+    }
+}
+
+async fn service_status(client: Client, namespace: &str, name: &str) -> anyhow::Result<bool> {
+    let services: Api<Service> = Api::namespaced(client, namespace);
+
+    match services.get_opt(name).await? {
+        Some(service) => {
+            let spec = service.spec.unwrap_or_default();
+            let mut service_type = spec.type_.unwrap_or_else(|| "ClusterIP".to_string());
+
+            if spec.cluster_ip.as_deref() == Some("None") {
+                service_type += " (headless)";
+            }
+
+            let ports: Vec<String> = spec
+                .ports
+                .unwrap_or_default()
+                .iter()
+                .map(|port| {
+                    let protocol = port.protocol.clone().unwrap_or_else(|| "TCP".to_string());
+                    match port.node_port {
+                        Some(node_port) => format!("{}:{}/{}", port.port, node_port, protocol),
+                        None => format!("{}/{}", port.port, protocol),
+                    }
+                })
+                .collect();
+
+            println!("  Service: {}, ports {}", service_type, ports.join(", "));
+            Ok(true)
+        }
+        None => {
+            println!("  Service: not found");
+            Ok(false)
+        }
+    }
+}
+
+async fn secret_status(client: Client, namespace: &str, name: &str) -> anyhow::Result<bool> {
+    let secrets: Api<Secret> = Api::namespaced(client, namespace);
+
+    if secrets.get_opt(name).await?.is_some() {
+        println!("  Secret: present");
+        Ok(true)
+    } else {
+        println!("  Secret: not found");
+        Ok(false)
+    }
+}
+
+async fn ingress_status(client: Client, namespace: &str, name: &str) -> anyhow::Result<bool> {
+    let ingress_res: Api<Ingress> = Api::namespaced(client.clone(), namespace);
+    let secrets: Api<Secret> = Api::namespaced(client, namespace);
+
+    let ingress = match ingress_res.get_opt(name).await? {
+        Some(ingress) => ingress,
+        None => {
+            println!("  Ingress: not found");
+            return Ok(false);
+        }
+    };
+
+    let spec = ingress.spec.unwrap_or_default();
+    let hosts: Vec<String> = spec
+        .rules
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|rule| rule.host.clone())
+        .collect();
+
+    println!("  Ingress: hosts {}", hosts.join(", "));
+
+    let mut healthy = true;
+
+    for tls in spec.tls.unwrap_or_default() {
+        if let Some(secret_name) = tls.secret_name {
+            if secrets.get_opt(&secret_name).await?.is_some() {
+                println!("  TLS secret {}: present", secret_name);
+            } else {
+                println!("  TLS secret {}: missing", secret_name);
+                healthy = false;
+            }
+        }
+    }
+
+    Ok(healthy)
+}
+
+/// Prints the state of every instance of the resources in the cluster, or only of the instance
+/// with the given name, returns whether all of them are healthy.
+pub async fn status(
+    client: Client,
+    namespace: &str,
+    config: &Config,
+    resources: &[SugarfungeResource],
+    instance: Option<&str>,
+) -> anyhow::Result<bool> {
+    let mut healthy = true;
+
+    let instances = resources.iter().flat_map(|resource| {
+        resources::names(config, *resource)
+            .into_iter()
+            .filter(|name| instance.is_none_or(|instance| name == instance))
+            .map(move |name| (resource, name))
+    });
+
+    for (resource, name) in instances {
+        println!("{} ({})", resource, name);
+
+        let resource_healthy = match resource {
+            SugarfungeResource::Ingress => ingress_status(client.clone(), namespace, &name).await?,
+            SugarfungeResource::Registry => secret_status(client.clone(), namespace, &name).await?,
+            SugarfungeResource::Node => {
+                let workload = statefulset_status(client.clone(), namespace, &name).await?;
+                let service = service_status(client.clone(), namespace, &name).await?;
+                workload && service
+            }
+            _ => {
+                let workload = deployment_status(client.clone(), namespace, &name).await?;
+                let service = service_status(client.clone(), namespace, &name).await?;
+                workload && service
+            }
+        };
+
+        healthy &= resource_healthy;
+    }
+
+    Ok(healthy)
+}
+
+/// Converts the object to a value without the status, which is owned by the cluster.
+fn comparable(object: &K8sObject) -> anyhow::Result<Value> {
+    let mut value = serde_json::to_value(object)?;
+
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("status");
+    }
+
+    Ok(value)
+}
+
+/// Live secrets return their values in `data` while the tool writes them in `stringData`.
+fn with_string_data(object: K8sObject) -> K8sObject {
+    match object {
+        K8sObject::Secret(mut secret) => {
+            if let Some(data) = secret.data.take() {
+                let mut string_data = secret.string_data.take().unwrap_or_default();
+
+                for (key, value) in data {
+                    string_data.insert(key, String::from_utf8_lossy(&value.0).to_string());
+                }
+
+                secret.string_data = Some(string_data);
+            }
+
+            K8sObject::Secret(secret)
+        }
+        object => object,
+    }
+}
+
+/// Keeps only the live fields that are set in the desired value, so the fields defaulted by
+/// the API server are not reported as changes.
+fn project(live: &Value, desired: &Value) -> Value {
+    match (live, desired) {
+        (Value::Object(live_fields), Value::Object(desired_fields)) => Value::Object(
+            desired_fields
+                .iter()
+                .filter_map(|(key, desired_value)| {
+                    live_fields
+                        .get(key)
+                        .map(|live_value| (key.to_string(), project(live_value, desired_value)))
+                })
+                .collect(),
+        ),
+        (Value::Array(live_items), Value::Array(desired_items)) => Value::Array(
+            live_items
+                .iter()
+                .enumerate()
+                .map(|(i, live_item)| match desired_items.get(i) {
+                    Some(desired_item) => project(live_item, desired_item),
+                    None => live_item.clone(),
+                })
+                .collect(),
+        ),
+        _ => live.clone(),
+    }
+}
+
+/// Masks the secret values so they are never printed, only whether they change.
+fn hide_secret_values(live: &mut Value, desired: &mut Value) {
+    let live_data = live
+        .get("stringData")
+        .and_then(|x| x.as_object())
+        .cloned()
+        .unwrap_or_default();
+
+    if let Some(desired_data) = desired
+        .get_mut("stringData")
+        .and_then(|x| x.as_object_mut())
+    {
+        for (key, value) in desired_data.iter_mut() {
+            *value = match live_data.get(key) {
+                Some(live_value) if live_value == value => Value::from("***"),
+                _ => Value::from("*** (after)"),
+            };
+        }
+    }
+
+    let desired_data = desired
+        .get("stringData")
+        .and_then(|x| x.as_object())
+        .cloned()
+        .unwrap_or_default();
+
+    if let Some(live_data) = live.get_mut("stringData").and_then(|x| x.as_object_mut()) {
+        for (key, value) in live_data.iter_mut() {
+            *value = match desired_data.get(key) {
+                Some(desired_value) if desired_value == "***" => Value::from("***"),
+                _ => Value::from("*** (before)"),
+            };
+        }
+    }
+}
+
+fn to_yaml(value: &Value) -> anyhow::Result<String> {
+    if value.is_null() {
+        return Ok(String::new());
+    }
+
+    Ok(serde_yaml::to_string(value)?)
+}
+
+/// Prints a unified diff between the live objects and the desired ones, returns whether
+/// anything would change. With `prune`, the managed objects missing from the desired ones are
+/// reported as well.
+pub async fn diff(
+    client: Client,
+    namespace: &str,
+    manifests: Vec<Manifest>,
+    prune: bool,
+    stack: Option<&StackConfig>,
+) -> anyhow::Result<bool> {
+    let mut changed = false;
+    let mut desired_objects: Vec<(K8sResource, String)> = vec![];
+
+    for manifest in manifests {
+        for object in manifest.objects {
+            let resource_type = object.resource_type();
+            let name = object.name();
+            let id = format!("{}/{}", resource_type, name);
+
+            let mut desired = comparable(&object)?;
+            let live_object =
+                get_resource(client.clone(), namespace, resource_type.clone(), &name).await?;
+
+            let mut live = match live_object {
+                Some(live_object) => {
+                    project(&comparable(&with_string_data(live_object))?, &desired)
+                }
+                None => {
+                    println!("{}: will be created", id);
+                    Value::Null
+                }
+            };
+
+            if resource_type == K8sResource::Secret {
+                hide_secret_values(&mut live, &mut desired);
+            }
+
+            let live_yaml = to_yaml(&live)?;
+            let desired_yaml = to_yaml(&desired)?;
+
+            if live_yaml != desired_yaml {
+                changed = true;
+                let text_diff = TextDiff::from_lines(&live_yaml, &desired_yaml);
+                print!(
+                    "{}",
+                    text_diff
+                        .unified_diff()
+                        .header(&format!("live/{}", id), &format!("desired/{}", id))
+                );
+            }
+
+            desired_objects.push((resource_type, name));
+        }
+    }
+
+    if prune {
+        for object in list_managed_resources(client, namespace, &stack_labels(stack)).await? {
+            if !desired_objects.contains(&(object.resource_type(), object.name())) {
+                changed = true;
+                println!(
+                    "{}/{}: exists but is no longer in the config",
+                    object.resource_type(),
+                    object.name()
+                );
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+fn is_deployment_available(deployment: Option<&Deployment>) -> bool {
+    let deployment = match deployment {
+        Some(deployment) => deployment,
+        None => return false,
+    };
+    let desired = deployment
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.replicas)
+        .unwrap_or(1);
+
+    match &deployment.status {
+        Some(status) => {
+            status.observed_generation >= deployment.metadata.generation
+                && status.updated_replicas.unwrap_or(0) >= desired
+                && status.available_replicas.unwrap_or(0) >= desired
+        }
+        None => false,
+    }
+}
+
+fn is_statefulset_ready(statefulset: Option<&StatefulSet>) -> bool {
+    let statefulset = match statefulset {
+        Some(statefulset) => statefulset,
+        None => return false,
+    };
+    let desired = statefulset
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.replicas)
+        .unwrap_or(1);
+
+    match &statefulset.status {
+        Some(status) => {
+            status.observed_generation >= statefulset.metadata.generation
+                && status.updated_replicas.unwrap_or(0) >= desired
+                && status.ready_replicas.unwrap_or(0) >= desired
+        }
+        None => false,
+    }
+}
+
+fn container_failures(pod_name: &str, statuses: &[ContainerStatus]) -> Vec<String> {
+    let mut failures: Vec<String> = vec![];
+
+    for status in statuses {
+        let state = status.state.clone().unwrap_or_default();
+        let last_state = status.last_state.clone().unwrap_or_default();
+
+        if let Some(waiting) = state.waiting {
+            if let Some(reason) = waiting.reason {
+                failures.push(format!(
+                    "{}/{}: {}: {}",
+                    pod_name,
+                    status.name,
+                    reason,
+                    waiting.message.unwrap_or_default()
+                ));
+            }
+        }
+
+        if let Some(terminated) = last_state.terminated {
+            failures.push(format!(
+                "{}/{}: last terminated with {} (exit code {})",
+                pod_name,
+                status.name,
+                terminated.reason.unwrap_or_default(),
+                terminated.exit_code
+            ));
+        }
+    }
+
+    failures
+}
+
+/// Reasons why the pods are not ready: unschedulable pods and waiting or crashed containers.
+fn pod_failures(pods: &[Pod]) -> Vec<String> {
+    let mut failures: Vec<String> = vec![];
+
+    for pod in pods {
+        let pod_name = pod.metadata.name.clone().unwrap_or_default();
+        let status = pod.status.clone().unwrap_or_default();
+
+        for condition in status.conditions.unwrap_or_default() {
+            if condition.type_ == "PodScheduled" && condition.status == "False" {
+                failures.push(format!(
+                    "{}: {}: {}",
+                    pod_name,
+                    condition.reason.unwrap_or_default(),
+                    condition.message.unwrap_or_default()
+                ));
+            }
+        }
+
+        failures.extend(container_failures(
+            &pod_name,
+            &status.init_container_statuses.unwrap_or_default(),
+        ));
+        failures.extend(container_failures(
+            &pod_name,
+            &status.container_statuses.unwrap_or_default(),
+        ));
+    }
+
+    failures
+}
+
+fn is_crash_looping(pod: &Pod) -> bool {
+    let status = pod.status.clone().unwrap_or_default();
+
+    status
+        .init_container_statuses
+        .unwrap_or_default()
+        .iter()
+        .chain(status.container_statuses.unwrap_or_default().iter())
+        .filter_map(|container| container.state.as_ref()?.waiting.as_ref()?.reason.as_ref())
+        .any(|reason| reason == "CrashLoopBackOff")
+}
+
+fn rollout_error(name: &str, message: &str, pods: &[Pod]) -> anyhow::Error {
+    let mut error = format!("{}: {}", name, message);
+
+    for failure in pod_failures(pods) {
+        error += "\n  ";
+        error += &failure;
+    }
+
+    anyhow::Error::msg(error)
+}
+
+async fn wait_for_workload<K>(
+    client: Client,
+    namespace: &str,
+    name: &str,
+    condition: impl Condition<K>,
+    timeout: Duration,
+) -> anyhow::Result<()>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug + Send + 'static,
+{
+    let workloads: Api<K> = Api::namespaced(client.clone(), namespace);
+    let pods: Api<Pod> = Api::namespaced(client, namespace);
+    let lp = ListParams::default().labels(&format!("app.kubernetes.io/name={}", name));
+
+    let ready = await_condition(workloads, name, condition);
+    let deadline = tokio::time::sleep(timeout);
+    let mut interval = tokio::time::interval(Duration::from_secs(5));
+    tokio::pin!(ready, deadline);
+
+    loop {
+        tokio::select! {
+            result = &mut ready => {
+                result?;
+                return Ok(());
+            }
+            _ = &mut deadline => {
+                let message = format!("timed out after {}s waiting for the rollout", timeout.as_secs());
+                return Err(rollout_error(name, &message, &pods.list(&lp).await?.items));
+            }
+            _ = interval.tick() => {
+                let pod_list = pods.list(&lp).await?.items;
+                if pod_list.iter().any(is_crash_looping) {
+                    return Err(rollout_error(name, "pods are in CrashLoopBackOff", &pod_list));
+                }
+            }
+        }
+    }
+}
+
+/// Blocks until the workload of the resource instance has all its replicas available.
+pub async fn wait(
+    client: Client,
+    namespace: &str,
+    resource: SugarfungeResource,
+    name: &str,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    if let SugarfungeResource::Ingress | SugarfungeResource::Registry = resource {
+        return Ok(());
+    }
+
+    println!("{} ({}): waiting for the rollout", resource, name);
+
+    match resource {
+        SugarfungeResource::Node => {
+            wait_for_workload(client, namespace, name, is_statefulset_ready, timeout).await
+        }
+        _ => wait_for_workload(client, namespace, name, is_deployment_available, timeout).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn project_keeps_the_desired_fields() {
+        let live = json!({
+            "metadata": {"name": "sf-api", "uid": "1234", "resourceVersion": "1"},
+            "spec": {
+                "replicas": 1,
+                "ports": [
+                    {"port": 4000, "protocol": "TCP"},
+                    {"port": 4001, "protocol": "TCP"},
+                ],
+            },
+        });
+        let desired = json!({
+            "metadata": {"name": "sf-api"},
+            "spec": {"ports": [{"port": 4000}], "selector": {"app": "sf-api"}},
+        });
+
+        // Extra live items are kept, since they are removed by the change.
+        assert_eq!(
+            project(&live, &desired),
+            json!({
+                "metadata": {"name": "sf-api"},
+                "spec": {"ports": [{"port": 4000}, {"port": 4001, "protocol": "TCP"}]},
+            })
+        );
+    }
+
+    #[test]
+    fn hide_secret_values_shows_the_changed_keys() {
+        let mut live = json!({"stringData": {"same": "a", "changed": "b", "removed": "c"}});
+        let mut desired = json!({"stringData": {"same": "a", "changed": "d", "added": "e"}});
+
+        hide_secret_values(&mut live, &mut desired);
+
+        assert_eq!(
+            live,
+            json!({"stringData": {"same": "***", "changed": "*** (before)", "removed": "*** (before)"}})
+        );
+        assert_eq!(
+            desired,
+            json!({"stringData": {"same": "***", "changed": "*** (after)", "added": "*** (after)"}})
+        );
+    }
+}
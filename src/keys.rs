@@ -0,0 +1,84 @@
+use ed25519_compact::{KeyPair, Seed};
+
+/// Lowercase hex of the bytes, as read by the node from its key files.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+pub fn from_hex(value: &str) -> anyhow::Result<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.chars().all(|x| x.is_ascii_hexdigit()) {
+        anyhow::bail!("not a hexadecimal string");
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&value[i..i + 2], 16)?))
+        .collect()
+}
+
+/// A new ed25519 secret key for the libp2p identity of a node, in hex.
+pub fn generate_node_key() -> String {
+    to_hex(&*Seed::generate())
+}
+
+/// A new secret seed for a session key, as a secret URI for `key insert`.
+pub fn generate_session_key() -> String {
+    "0x".to_string() + &to_hex(&*Seed::generate())
+}
+
+/// The libp2p peer id of the node key: the identity multihash of the protobuf encoded public
+/// key, in base58.
+pub fn peer_id(node_key: &str) -> anyhow::Result<String> {
+    let seed = Seed::from_slice(&from_hex(node_key)?)
+        .map_err(|_| anyhow::Error::msg("a node key must be 32 bytes"))?;
+    let public_key = KeyPair::from_seed(seed).pk;
+
+    // Key type 1 (Ed25519) followed by the 32 bytes of the key.
+    let mut encoded_key = vec![0x08, 0x01, 0x12, 0x20];
+    encoded_key.extend_from_slice(&*public_key);
+
+    let mut multihash = vec![0x00, encoded_key.len() as u8];
+    multihash.extend(encoded_key);
+
+    Ok(bs58::encode(multihash).into_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(from_hex("00ff10").unwrap(), [0x00, 0xff, 0x10]);
+        assert_eq!(to_hex(&[0x00, 0xff, 0x10]), "00ff10");
+        assert_eq!(from_hex("00FF10").unwrap(), [0x00, 0xff, 0x10]);
+
+        assert!(from_hex("0").is_err());
+        assert!(from_hex("0g").is_err());
+    }
+
+    #[test]
+    fn peer_id_of_a_node_key() {
+        let node_key = "00".repeat(31) + "01";
+
+        assert_eq!(
+            peer_id(&node_key).unwrap(),
+            "12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp"
+        );
+        assert!(peer_id("01").is_err());
+    }
+
+    #[test]
+    fn generated_node_keys() {
+        let node_key = generate_node_key();
+        assert_eq!(from_hex(&node_key).unwrap().len(), 32);
+        assert!(peer_id(&node_key).is_ok());
+    }
+
+    #[test]
+    fn generated_session_keys() {
+        let session_key = generate_session_key();
+        assert!(session_key.starts_with("0x"));
+        assert_eq!(from_hex(&session_key[2..]).unwrap().len(), 32);
+    }
+}
@@ -1,12 +1,18 @@
-use std::fs::File;
+use std::{fs, path::Path, time::Duration};
 
-use clap::{ArgEnum, Parser};
-use config::Config;
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use config::{select, Config, ConfigFormat, ConfigProblem};
 use derive_more::Display;
-use ron::de::from_reader;
-use utils::{delete_resources, K8sResource};
+use kube::Client;
+use resources::Manifest;
+use serde_json::Value;
+use utils::{
+    apply_resources, create_resources, delete_resources, delete_volume_claims, to_yaml, K8sResource,
+};
 
 pub mod config;
+pub mod inspect;
+pub mod keys;
 pub mod resources;
 pub mod utils;
 
@@ -18,6 +24,7 @@ pub enum SugarfungeChainType {
 
 #[derive(ArgEnum, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Display)]
 pub enum SugarfungeResource {
+    Registry,
     Api,
     Explorer,
     Ipfs,
@@ -27,10 +34,147 @@ pub enum SugarfungeResource {
     Ingress,
 }
 
-#[derive(ArgEnum, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Args, Debug, Copy, Clone)]
+struct WaitArgs {
+    // Wait until the workloads are ready
+    #[clap(long)]
+    wait: bool,
+
+    // Seconds to wait for the rollout of each service
+    #[clap(long, default_value = "300")]
+    timeout: u64,
+}
+
+impl WaitArgs {
+    fn timeout(&self) -> Option<Duration> {
+        self.wait.then(|| Duration::from_secs(self.timeout))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Write a config file with the defaults of every service
+    Init {
+        // Path of the config file to write
+        #[clap(default_value = "config.ron")]
+        path: String,
+
+        // Overwrite the file if it already exists
+        #[clap(long)]
+        force: bool,
+    },
+    /// Check the config for invalid names, ports and keys
+    Validate,
+    /// Print the effective config after merging the config files on top of the defaults
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum KeysAction {
+    /// Print the peer id of every node, from its config or from the node key stored in the cluster
+    Node,
+    /// Generate the aura and grandpa session keys of the validators without ones
+    Session {
+        /// Name of the node without the stack prefix, every node when omitted
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
 enum CliAction {
-    Create,
-    Delete,
+    /// Create the objects of a service
+    Create {
+        /// Name of the service
+        #[clap(arg_enum)]
+        service: SugarfungeResource,
+
+        /// Name of the instance without the stack prefix, every instance of the service when omitted
+        name: Option<String>,
+
+        // Keep the objects already created when create fails, for debugging
+        #[clap(long)]
+        keep_on_failure: bool,
+
+        #[clap(flatten)]
+        wait: WaitArgs,
+    },
+    /// Create or update the objects of a service with server-side apply
+    Apply {
+        /// Name of the service
+        #[clap(arg_enum)]
+        service: SugarfungeResource,
+
+        /// Name of the instance without the stack prefix, every instance of the service when omitted
+        name: Option<String>,
+
+        #[clap(flatten)]
+        wait: WaitArgs,
+    },
+    /// Delete the objects of a service
+    Delete {
+        /// Name of the service
+        #[clap(arg_enum)]
+        service: SugarfungeResource,
+
+        /// Name of the instance without the stack prefix, every instance of the service when omitted
+        name: Option<String>,
+
+        // Also delete the volumes holding the chain data and the stored node keys of the nodes
+        #[clap(long)]
+        purge_data: bool,
+    },
+    /// Apply every service in the config in dependency order
+    Up {
+        #[clap(flatten)]
+        wait: WaitArgs,
+    },
+    /// Delete every service in the config in reverse dependency order
+    Down {
+        // Also delete the volumes holding the chain data and the stored node keys of the nodes
+        #[clap(long)]
+        purge_data: bool,
+    },
+    /// Show the state of the services in the cluster
+    Status {
+        /// Name of the service, every service when omitted
+        #[clap(arg_enum)]
+        service: Option<SugarfungeResource>,
+
+        /// Name of the instance without the stack prefix, every instance of the service when omitted
+        name: Option<String>,
+    },
+    /// Compare the objects built from the config with the live objects
+    Diff {
+        /// Name of the service, every service when omitted
+        #[clap(arg_enum)]
+        service: Option<SugarfungeResource>,
+
+        /// Name of the instance without the stack prefix, every instance of the service when omitted
+        name: Option<String>,
+    },
+    /// Print the objects built from the config as YAML without contacting the cluster
+    Render {
+        /// Name of the service, every service when omitted
+        #[clap(arg_enum)]
+        service: Option<SugarfungeResource>,
+
+        /// Name of the instance without the stack prefix, every instance of the service when omitted
+        name: Option<String>,
+
+        // Directory to write the rendered manifests, stdout is used when omitted
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Manage the config file
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+    /// Generate the node keys and session keys of the nodes
+    Keys {
+        #[clap(subcommand)]
+        action: KeysAction,
+    },
 }
 
 /// Manage your SugarFunge Infrastructure in Kubernetes
@@ -38,40 +182,443 @@ enum CliAction {
 #[clap(author, version, about, long_about = None)]
 struct Cli {
     // Action to take on the service or the config file
-    #[clap(arg_enum)]
+    #[clap(subcommand)]
     action: CliAction,
 
-    /// Name of the service
-    #[clap(arg_enum)]
-    service: SugarfungeResource,
-
     // Namespace to apply the action
-    #[clap(short, long, default_value = "default")]
+    #[clap(short, long, global = true, default_value = "default")]
     namespace: String,
 
     // Chain type to configure
-    #[clap(long, arg_enum)]
+    #[clap(long, global = true, arg_enum)]
     chain: Option<SugarfungeChainType>,
 
-    // Configuration file when creating the service
-    #[clap(long)]
-    config: Option<String>,
+    // Configuration files when creating the service, each one merged on top of the previous ones
+    #[clap(long, global = true, number_of_values = 1)]
+    config: Vec<String>,
+
+    // Format of the config files, detected from the file extension when omitted
+    #[clap(long, global = true, arg_enum)]
+    config_format: Option<ConfigFormat>,
+
+    // Kubeconfig file to use instead of the default one
+    #[clap(long, global = true)]
+    kubeconfig: Option<String>,
+
+    // Kubeconfig context to use, overrides the context in the config file
+    #[clap(long, global = true)]
+    context: Option<String>,
+}
+
+/// The given service, or every service when omitted.
+fn selected_services(service: Option<SugarfungeResource>) -> Vec<SugarfungeResource> {
+    match service {
+        Some(service) => vec![service],
+        None => resources::RESOURCES.to_vec(),
+    }
+}
+
+/// Exits when the service has no instance in the config, or none with the given name.
+fn check_configured(config: &Config, service: SugarfungeResource, name: Option<&str>) {
+    let names = resources::names(config, service);
+
+    match name {
+        Some(name) if !names.iter().any(|x| x == name) => {
+            println!("{}: no instance named {} in the config", service, name);
+            std::process::exit(1);
+        }
+        None if names.is_empty() => {
+            println!("failed to load config for {}", service);
+            std::process::exit(1);
+        }
+        _ => {}
+    }
+}
+
+fn render(
+    config: &Config,
+    chain: SugarfungeChainType,
+    service: Option<SugarfungeResource>,
+    name: Option<&str>,
+    output: Option<String>,
+) -> anyhow::Result<()> {
+    if let Some(service) = service {
+        check_configured(config, service, name);
+    }
+
+    let services = selected_services(service);
+    let manifests = resources::manifests(config, chain, &services, name);
+
+    match output {
+        Some(dir) => {
+            fs::create_dir_all(&dir)?;
+            // One file per service with the objects of all its instances.
+            for resource in services {
+                let objects: Vec<_> = manifests
+                    .iter()
+                    .filter(|x| x.resource == resource)
+                    .flat_map(|x| x.objects.clone())
+                    .collect();
+
+                if !objects.is_empty() {
+                    let file_name = resource.to_string().to_lowercase() + ".yaml";
+                    fs::write(Path::new(&dir).join(file_name), to_yaml(&objects)?)?;
+                }
+            }
+        }
+        None => {
+            let objects: Vec<_> = manifests.into_iter().flat_map(|x| x.objects).collect();
+            print!("{}", to_yaml(&objects)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn config_init(chain: SugarfungeChainType, path: &str, force: bool) -> anyhow::Result<()> {
+    if Path::new(path).exists() && !force {
+        println!("{} already exists, use --force to overwrite it", path);
+        std::process::exit(1);
+    }
+
+    // The node keys are written next to the config, so it can be committed without them. An
+    // existing key file is kept, since the peer id of the node changes with its key.
+    let mut config = Config::for_chain(chain);
+    for node in &mut config.node {
+        let key_path = Path::new(path).with_file_name(node.name.to_string() + ".node-key");
+        if !key_path.exists() {
+            fs::write(&key_path, keys::generate_node_key())?;
+            println!("{}: node key written", key_path.display());
+        }
+        node.node_key = Some(format!("file:{}", key_path.display()));
+    }
+
+    fs::write(path, config.to_ron()?)?;
+    println!("{}: config written", path);
+
+    Ok(())
+}
+
+/// Gives the nodes without a node key the one stored in the cluster. Unless `generate` is false,
+/// the selected nodes without a stored key get a new one, stored when they are created, so every
+/// node keeps its peer id and the other nodes can reach it as a bootnode.
+async fn fill_node_keys(
+    client: Client,
+    namespace: &str,
+    config: &mut Config,
+    services: &[SugarfungeResource],
+    name: Option<&str>,
+    generate: bool,
+) -> anyhow::Result<()> {
+    if !services.contains(&SugarfungeResource::Node) {
+        return Ok(());
+    }
+
+    for node in config.node.iter_mut().filter(|x| x.node_key.is_none()) {
+        let stored =
+            resources::node::stored_node_key(client.clone(), namespace, &node.name).await?;
+        node.node_key = match stored {
+            Some(node_key) => Some(node_key),
+            None if generate && name.is_none_or(|name| name == node.name) => {
+                Some(keys::generate_node_key())
+            }
+            None => None,
+        };
+    }
+
+    Ok(())
+}
+
+/// Prints the peer id of every node, from the node key in its config or stored in the cluster.
+async fn node_keys(client: Client, namespace: &str, config: &mut Config) -> anyhow::Result<()> {
+    let services = [SugarfungeResource::Node];
+    fill_node_keys(client, namespace, config, &services, None, false).await?;
+
+    for node in &config.node {
+        match &node.node_key {
+            Some(node_key) => println!("{}: peer id {}", node.name, keys::peer_id(node_key)?),
+            None => println!(
+                "{}: no node key, one is generated when the node is created",
+                node.name
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints new session keys for the validators without ones, to add to the config.
+fn session_keys(config: &Config, name: Option<&str>) {
+    for node in select(&config.node, name) {
+        match &node.session_keys {
+            Some(_) => println!("{}: session keys in the config", node.name),
+            None => println!(
+                "{}: set session_keys: aura: {:?}, grandpa: {:?} in its config",
+                node.name,
+                keys::generate_session_key(),
+                keys::generate_session_key()
+            ),
+        }
+    }
+}
+
+/// Prints the problems and exits when there is any.
+fn exit_on_problems(problems: Vec<ConfigProblem>) {
+    if problems.is_empty() {
+        return;
+    }
+
+    println!("Invalid config:");
+    for problem in problems {
+        println!("  {}", problem);
+    }
+    std::process::exit(1);
+}
+
+/// Reports every problem in the config and exits when there is any. The chainspec secrets of the
+/// testnet nodes are only checked when a client is given and the node is one of the services.
+async fn validate(
+    client: Option<Client>,
+    namespace: &str,
+    chain: SugarfungeChainType,
+    config: &Config,
+    services: &[SugarfungeResource],
+    name: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut problems = config.validate();
+
+    if let Some(client) = client {
+        if services.contains(&SugarfungeResource::Node) {
+            for (i, node_config) in config.node.iter().enumerate() {
+                if name.is_some_and(|name| name != node_config.name) {
+                    continue;
+                }
+
+                let result = resources::node::check_chainspec_secret(
+                    client.clone(),
+                    namespace,
+                    chain,
+                    node_config,
+                )
+                .await;
+
+                if let Err(e) = result {
+                    problems.push(ConfigProblem {
+                        path: format!("node[{}].chainspec_ext", i),
+                        message: format!(
+                            "required for the testnet chain without the secret: {}",
+                            e
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    exit_on_problems(problems);
+
+    Ok(())
+}
+
+/// Checks the chainspec secret of the node instance before its objects are sent.
+async fn check_chainspec_secret(
+    client: Client,
+    namespace: &str,
+    chain: SugarfungeChainType,
+    config: &Config,
+    manifest: &Manifest,
+) -> anyhow::Result<()> {
+    if manifest.resource == SugarfungeResource::Node {
+        for node_config in select(&config.node, Some(&manifest.name)) {
+            resources::node::check_chainspec_secret(client.clone(), namespace, chain, node_config)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates every instance of the service, or only the instance with the given name.
+async fn create(
+    client: Client,
+    namespace: &str,
+    chain: SugarfungeChainType,
+    config: &Config,
+    service: SugarfungeResource,
+    name: Option<&str>,
+    keep_on_failure: bool,
+) -> anyhow::Result<()> {
+    // The ingress of create routes to the ports of the live services.
+    if service == SugarfungeResource::Ingress {
+        let resources: Vec<SugarfungeResource> = resources::SERVICES.to_vec();
+        let config = config.clone();
+        return resources::ingress::create(client, namespace, config, resources, keep_on_failure)
+            .await;
+    }
+
+    for manifest in resources::manifests(config, chain, &[service], name) {
+        check_chainspec_secret(client.clone(), namespace, chain, config, &manifest).await?;
+        create_resources(client.clone(), namespace, manifest.objects, keep_on_failure).await?;
+    }
+
+    Ok(())
+}
+
+async fn apply(
+    client: Client,
+    namespace: &str,
+    chain: SugarfungeChainType,
+    config: &Config,
+    services: &[SugarfungeResource],
+    name: Option<&str>,
+    wait: Option<Duration>,
+) -> anyhow::Result<()> {
+    // The ingress routes to the service ports in the config, which are the ones applied.
+    for manifest in resources::manifests(config, chain, services, name) {
+        check_chainspec_secret(client.clone(), namespace, chain, config, &manifest).await?;
+
+        println!("{} ({}): applying", manifest.resource, manifest.name);
+        apply_resources(client.clone(), namespace, manifest.objects).await?;
+
+        // Wait for each instance before the next one, so the services depending on it can start.
+        if let Some(timeout) = wait {
+            inspect::wait(
+                client.clone(),
+                namespace,
+                manifest.resource,
+                &manifest.name,
+                timeout,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes every instance of the service, or only the instance with the given name. The chain
+/// data and the node key of the nodes are kept unless `purge_data` is set.
+async fn delete(
+    client: Client,
+    namespace: &str,
+    config: &Config,
+    service: SugarfungeResource,
+    name: Option<&str>,
+    purge_data: bool,
+) -> anyhow::Result<()> {
+    let resource_types = match service {
+        SugarfungeResource::Registry => vec![K8sResource::Secret],
+        SugarfungeResource::Api => vec![
+            K8sResource::Service,
+            K8sResource::ConfigMap,
+            K8sResource::Deployment,
+        ],
+        SugarfungeResource::Explorer => vec![
+            K8sResource::Service,
+            K8sResource::ConfigMap,
+            K8sResource::Deployment,
+        ],
+        SugarfungeResource::Ipfs => vec![
+            K8sResource::Service,
+            K8sResource::ConfigMap,
+            K8sResource::Secret,
+            K8sResource::Deployment,
+        ],
+        SugarfungeResource::Keycloak => vec![
+            K8sResource::Service,
+            K8sResource::ConfigMap,
+            K8sResource::Secret,
+            K8sResource::Deployment,
+        ],
+        SugarfungeResource::Node => vec![
+            K8sResource::Service,
+            K8sResource::ConfigMap,
+            K8sResource::StatefulSet,
+        ],
+        SugarfungeResource::Status => vec![
+            K8sResource::Service,
+            K8sResource::ConfigMap,
+            K8sResource::Deployment,
+        ],
+        SugarfungeResource::Ingress => vec![K8sResource::Ingress],
+    };
+
+    for instance in resources::names(config, service) {
+        if name.is_none_or(|name| name == instance) {
+            let resource_types = resource_types.clone();
+            delete_resources(client.clone(), namespace, &instance, resource_types).await?;
+
+            // Objects of the node named after the instance with a suffix.
+            if service == SugarfungeResource::Node {
+                for (object_name, resource_type) in [
+                    (
+                        resources::node::session_keys_secret_name(&instance),
+                        K8sResource::Secret,
+                    ),
+                    (
+                        resources::node::p2p_service_name(&instance),
+                        K8sResource::Service,
+                    ),
+                ] {
+                    let resource_types = vec![resource_type];
+                    delete_resources(client.clone(), namespace, &object_name, resource_types)
+                        .await?;
+                }
+            }
+
+            // The node key goes with the chain data, so a redeployed node keeps its peer id.
+            if service == SugarfungeResource::Node && purge_data {
+                let object_name = resources::node::node_key_secret_name(&instance);
+                let resource_types = vec![K8sResource::Secret];
+                delete_resources(client.clone(), namespace, &object_name, resource_types).await?;
+                delete_volume_claims(client.clone(), namespace, &instance).await?;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let mut config = Config::default();
+    let mut layers: Vec<Value> = vec![];
+
+    for config_path in &cli.config {
+        let format = cli
+            .config_format
+            .unwrap_or_else(|| ConfigFormat::from_path(config_path));
+        let layer = fs::read_to_string(config_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|x| format.parse(&x));
 
-    if let Some(config_path) = cli.config {
-        let file = File::open(config_path).expect("Cannot find config file");
-        config = match from_reader(file) {
-            Ok(x) => x,
+        match layer {
+            Ok(x) => layers.push(x),
             Err(e) => {
-                println!("Failed to load config: {}", e);
+                println!("Failed to load config {}: {}", config_path, e);
                 std::process::exit(1);
             }
-        };
+        }
+    }
+
+    let mut config = match Config::from_layers(layers) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // `config show` prints the references instead of the secrets they point to.
+    let show = matches!(
+        cli.action,
+        CliAction::Config {
+            action: ConfigAction::Show
+        }
+    );
+    if !show {
+        exit_on_problems(config.resolve_references());
+        config.apply_stack_prefix();
     }
 
     let mut chain = SugarfungeChainType::Local;
@@ -80,125 +627,222 @@ async fn main() -> anyhow::Result<()> {
         chain = chain_type;
     }
 
-    let error_message = format!("failed to load config for {}", cli.service);
+    // The client is only awaited by the actions using the cluster, so render stays offline.
+    let context = cli.context.or_else(|| config.context.clone());
+    let client = utils::client(cli.kubeconfig, context);
 
-    match cli.service {
-        SugarfungeResource::Api => match cli.action {
-            CliAction::Create => {
-                let api_config = config.api.expect(&error_message);
-                resources::api::deployment(&cli.namespace, api_config).await?;
-                Ok(())
-            }
-            CliAction::Delete => {
-                let api_name = config.api.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![
-                    K8sResource::Service,
-                    K8sResource::ConfigMap,
-                    K8sResource::Deployment,
-                ];
-                Ok(delete_resources(&cli.namespace, &api_name, resource_types).await?)
-            }
-        },
-        SugarfungeResource::Explorer => match cli.action {
-            CliAction::Create => {
-                let explorer_config = config.explorer.expect(&error_message);
-                resources::explorer::deployment(&cli.namespace, explorer_config).await?;
-                Ok(())
+    match cli.action {
+        CliAction::Create {
+            service,
+            name,
+            keep_on_failure,
+            wait,
+        } => {
+            let client = client.await?;
+            let namespace = &cli.namespace;
+            let name = name.map(|x| config.prefixed(&x));
+            let name = name.as_deref();
+            check_configured(&config, service, name);
+            let services = &[service];
+            fill_node_keys(client.clone(), namespace, &mut config, services, name, true).await?;
+            validate(
+                Some(client.clone()),
+                namespace,
+                chain,
+                &config,
+                &[service],
+                name,
+            )
+            .await?;
+            create(
+                client.clone(),
+                namespace,
+                chain,
+                &config,
+                service,
+                name,
+                keep_on_failure,
+            )
+            .await?;
+            if let Some(timeout) = wait.timeout() {
+                for instance in resources::names(&config, service) {
+                    if name.is_none_or(|name| name == instance) {
+                        inspect::wait(client.clone(), namespace, service, &instance, timeout)
+                            .await?;
+                    }
+                }
             }
-            CliAction::Delete => {
-                let explorer_name = config.explorer.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![
-                    K8sResource::Service,
-                    K8sResource::ConfigMap,
-                    K8sResource::Deployment,
-                ];
-                Ok(delete_resources(&cli.namespace, &explorer_name, resource_types).await?)
+            Ok(())
+        }
+        CliAction::Apply {
+            service,
+            name,
+            wait,
+        } => {
+            let client = client.await?;
+            let name = name.map(|x| config.prefixed(&x));
+            let name = name.as_deref();
+            check_configured(&config, service, name);
+            let services = &[service];
+            let namespace = &cli.namespace;
+            fill_node_keys(client.clone(), namespace, &mut config, services, name, true).await?;
+            validate(
+                Some(client.clone()),
+                &cli.namespace,
+                chain,
+                &config,
+                services,
+                name,
+            )
+            .await?;
+            let timeout = wait.timeout();
+            apply(
+                client,
+                &cli.namespace,
+                chain,
+                &config,
+                services,
+                name,
+                timeout,
+            )
+            .await
+        }
+        CliAction::Delete {
+            service,
+            name,
+            purge_data,
+        } => {
+            let client = client.await?;
+            let name = name.map(|x| config.prefixed(&x));
+            let name = name.as_deref();
+            check_configured(&config, service, name);
+            delete(client, &cli.namespace, &config, service, name, purge_data).await
+        }
+        CliAction::Up { wait } => {
+            let client = client.await?;
+            let services = &resources::RESOURCES;
+            let namespace = &cli.namespace;
+            fill_node_keys(client.clone(), namespace, &mut config, services, None, true).await?;
+            validate(
+                Some(client.clone()),
+                &cli.namespace,
+                chain,
+                &config,
+                services,
+                None,
+            )
+            .await?;
+            apply(
+                client,
+                &cli.namespace,
+                chain,
+                &config,
+                services,
+                None,
+                wait.timeout(),
+            )
+            .await
+        }
+        CliAction::Down { purge_data } => {
+            let client = client.await?;
+            for service in resources::RESOURCES.iter().rev() {
+                if resources::is_configured(&config, *service) {
+                    println!("{}: deleting", service);
+                    let namespace = &cli.namespace;
+                    delete(
+                        client.clone(),
+                        namespace,
+                        &config,
+                        *service,
+                        None,
+                        purge_data,
+                    )
+                    .await?;
+                }
             }
-        },
-        SugarfungeResource::Ipfs => match cli.action {
-            CliAction::Create => {
-                let ipfs_config = config.ipfs.expect(&error_message);
-                resources::ipfs::deployment(&cli.namespace, ipfs_config).await?;
-                Ok(())
+            Ok(())
+        }
+        CliAction::Status { service, name } => {
+            let services = selected_services(service);
+            let name = name.map(|x| config.prefixed(&x));
+            let name = name.as_deref();
+            if let Some(service) = service {
+                check_configured(&config, service, name);
             }
-            CliAction::Delete => {
-                let ipfs_name = config.ipfs.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![
-                    K8sResource::Service,
-                    K8sResource::ConfigMap,
-                    K8sResource::Secret,
-                    K8sResource::Deployment,
-                ];
-                Ok(delete_resources(&cli.namespace, &ipfs_name, resource_types).await?)
+            let client = client.await?;
+            if !inspect::status(client, &cli.namespace, &config, &services, name).await? {
+                std::process::exit(1);
             }
-        },
-        SugarfungeResource::Keycloak => match cli.action {
-            CliAction::Create => {
-                let keycloak_config = config.keycloak.expect(&error_message);
-                resources::keycloak::deployment(&cli.namespace, keycloak_config).await?;
-                Ok(())
+            Ok(())
+        }
+        CliAction::Diff { service, name } => {
+            let name = name.map(|x| config.prefixed(&x));
+            let name = name.as_deref();
+            if let Some(service) = service {
+                check_configured(&config, service, name);
             }
-            CliAction::Delete => {
-                let keycloak_name = config.keycloak.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![
-                    K8sResource::Service,
-                    K8sResource::ConfigMap,
-                    K8sResource::Secret,
-                    K8sResource::Deployment,
-                ];
-                Ok(delete_resources(&cli.namespace, &keycloak_name, resource_types).await?)
+            let client = client.await?;
+            let services = selected_services(service);
+            let namespace = &cli.namespace;
+            fill_node_keys(
+                client.clone(),
+                namespace,
+                &mut config,
+                &services,
+                name,
+                true,
+            )
+            .await?;
+            let manifests = resources::manifests(&config, chain, &services, name);
+            // Objects no longer in the config are only reported when diffing every instance.
+            let prune = service.is_none() && name.is_none();
+            if inspect::diff(
+                client,
+                &cli.namespace,
+                manifests,
+                prune,
+                config.stack.as_ref(),
+            )
+            .await?
+            {
+                std::process::exit(1);
             }
-        },
-        SugarfungeResource::Node => match cli.action {
-            CliAction::Create => {
-                let node_config = config.node.expect(&error_message);
-                resources::node::statefulset(&cli.namespace, chain, node_config).await?;
+            Ok(())
+        }
+        CliAction::Render {
+            service,
+            name,
+            output,
+        } => {
+            let name = name.map(|x| config.prefixed(&x));
+            render(&config, chain, service, name.as_deref(), output)
+        }
+        CliAction::Config { action } => match action {
+            ConfigAction::Init { path, force } => config_init(chain, &path, force),
+            ConfigAction::Validate => {
+                // Only the testnet chain needs the cluster, to look up the chainspec secret.
+                let client = match chain {
+                    SugarfungeChainType::Testnet => Some(client.await?),
+                    SugarfungeChainType::Local => None,
+                };
+                let services = &resources::RESOURCES;
+                validate(client, &cli.namespace, chain, &config, services, None).await?;
+                println!("Config is valid");
                 Ok(())
             }
-            CliAction::Delete => {
-                let node_name = config.node.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![
-                    K8sResource::Service,
-                    K8sResource::ConfigMap,
-                    K8sResource::StatefulSet,
-                ];
-                Ok(delete_resources(&cli.namespace, &node_name, resource_types).await?)
-            }
-        },
-        SugarfungeResource::Status => match cli.action {
-            CliAction::Create => {
-                let status_config = config.status.expect(&error_message);
-                resources::status::deployment(&cli.namespace, status_config).await?;
+            ConfigAction::Show => {
+                print!("{}", config.to_ron()?);
                 Ok(())
             }
-            CliAction::Delete => {
-                let status_name = config.status.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![
-                    K8sResource::Service,
-                    K8sResource::ConfigMap,
-                    K8sResource::Deployment,
-                ];
-                Ok(delete_resources(&cli.namespace, &status_name, resource_types).await?)
-            }
         },
-        SugarfungeResource::Ingress => match cli.action {
-            CliAction::Create => {
-                let resources: Vec<SugarfungeResource> = vec![
-                    SugarfungeResource::Api,
-                    SugarfungeResource::Explorer,
-                    SugarfungeResource::Ipfs,
-                    SugarfungeResource::Keycloak,
-                    SugarfungeResource::Node,
-                    SugarfungeResource::Status,
-                ];
-                resources::ingress::create(&cli.namespace, config, resources).await?;
+        CliAction::Keys { action } => match action {
+            KeysAction::Node => node_keys(client.await?, &cli.namespace, &mut config).await,
+            KeysAction::Session { name } => {
+                let name = name.map(|x| config.prefixed(&x));
+                check_configured(&config, SugarfungeResource::Node, name.as_deref());
+                session_keys(&config, name.as_deref());
                 Ok(())
             }
-            CliAction::Delete => {
-                let ingress_name = config.ingress.expect(&error_message).name;
-                let resource_types: Vec<K8sResource> = vec![K8sResource::Ingress];
-                Ok(delete_resources(&cli.namespace, &ingress_name, resource_types).await?)
-            }
         },
     }
 }
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec, DeploymentStatus},
+        apps::v1::{Deployment, DeploymentSpec},
         core::v1::{
             ConfigMapEnvSource, Container, ContainerPort, EnvFromSource, PodSpec, PodTemplateSpec,
             ServicePort,
@@ -11,15 +11,14 @@ use k8s_openapi::{
     apimachinery::pkg::{apis::meta::v1::LabelSelector, util::intstr::IntOrString},
 };
 
-use kube::{
-    api::{Api, PostParams},
-    core::ObjectMeta,
-    Client,
-};
+use kube::core::ObjectMeta;
 
 use crate::{
     config::ApiConfig,
-    utils::{create_configmap, create_service, ServiceData},
+    utils::{
+        configmap, image_pull_secrets, labels, pod_scheduling, resource_requirements, service,
+        with_pod_extras, K8sObject, ServiceData,
+    },
 };
 
 fn container(config: ApiConfig) -> Container {
@@ -48,19 +47,15 @@ fn container(config: ApiConfig) -> Container {
             "$(NODE_URL)".to_string(),
         ]),
         ports: Some(vec![container_port]),
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
 
-pub async fn deployment(namespace: &str, config: ApiConfig) -> anyhow::Result<Deployment> {
-    let client = Client::try_default().await?;
-
+pub fn manifests(config: &ApiConfig) -> Vec<K8sObject> {
     let metadata = ObjectMeta {
         name: Some(config.name.to_string()),
-        labels: Some(BTreeMap::from([(
-            "app.kubernetes.io/name".to_string(),
-            config.name.to_string(),
-        )])),
+        labels: Some(labels(&config.name)),
         ..Default::default()
     };
 
@@ -76,15 +71,11 @@ pub async fn deployment(namespace: &str, config: ApiConfig) -> anyhow::Result<De
         ..Default::default()
     };
 
-    let _service =
-        create_service(client.clone(), namespace, metadata.clone(), service_data).await?;
+    let service = service(metadata.clone(), service_data);
 
     let configmap_data = BTreeMap::from([("NODE_URL".to_string(), config.node_url.to_owned())]);
 
-    let _configmap =
-        create_configmap(client.clone(), namespace, metadata.clone(), configmap_data).await?;
-
-    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let configmap = configmap(metadata.clone(), configmap_data);
 
     let container = container(config.clone());
 
@@ -93,10 +84,14 @@ pub async fn deployment(namespace: &str, config: ApiConfig) -> anyhow::Result<De
         spec: Some(DeploymentSpec {
             template: PodTemplateSpec {
                 metadata: Some(metadata.clone()),
-                spec: Some(PodSpec {
-                    containers: vec![container],
-                    ..Default::default()
-                }),
+                spec: Some(with_pod_extras(
+                    PodSpec {
+                        containers: vec![container],
+                        image_pull_secrets: image_pull_secrets(&config.pod.image_pull_secrets),
+                        ..pod_scheduling(&config.pod.scheduling)
+                    },
+                    &config.pod,
+                )),
             },
             selector: LabelSelector {
                 match_labels: Some(BTreeMap::from([(
@@ -107,9 +102,12 @@ pub async fn deployment(namespace: &str, config: ApiConfig) -> anyhow::Result<De
             },
             ..Default::default()
         }),
-        status: Some(DeploymentStatus::default()),
+        status: None,
     };
 
-    let pp = PostParams::default();
-    deployments.create(&pp, &api).await.map_err(|e| e.into())
+    vec![
+        K8sObject::Service(service),
+        K8sObject::ConfigMap(configmap),
+        K8sObject::Deployment(api),
+    ]
 }
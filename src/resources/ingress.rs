@@ -4,12 +4,17 @@ use k8s_openapi::api::{
     core::v1::Service,
     networking::v1::{
         HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
-        IngressServiceBackend, IngressSpec, IngressStatus, IngressTLS, ServiceBackendPort,
+        IngressServiceBackend, IngressSpec, IngressTLS, ServiceBackendPort,
     },
 };
-use kube::{api::PostParams, core::ObjectMeta, Api, Client};
+use kube::{core::ObjectMeta, Api, Client};
 
-use crate::{config::Config, SugarfungeResource};
+use crate::{
+    config::{Config, IngressConfig},
+    resources::{names, with_stack_metadata},
+    utils::{create_resources, labels, K8sObject},
+    SugarfungeResource,
+};
 
 pub async fn get_service_port(client: Client, namespace: &str, name: &str) -> i32 {
     let services: Api<Service> = Api::namespaced(client, namespace);
@@ -38,66 +43,12 @@ pub async fn get_service_port(client: Client, namespace: &str, name: &str) -> i3
     }
 }
 
-pub async fn create(
-    namespace: &str,
-    config: Config,
-    resources: Vec<SugarfungeResource>,
-) -> anyhow::Result<Ingress> {
-    let client = Client::try_default().await?;
-    let ingress_res: Api<Ingress> = Api::namespaced(client.clone(), namespace);
-    let ingress_error = format!("failed to load config for {}", SugarfungeResource::Ingress);
-    let ingress_config = config.ingress.clone().expect(&ingress_error);
+/// Builds the ingress routing each `(service name, service port)` backend to a subdomain of the host.
+pub fn ingress(ingress_config: &IngressConfig, backends: Vec<(String, i32)>) -> Ingress {
     let mut tls_hosts: Vec<String> = vec![];
     let mut rules: Vec<IngressRule> = vec![];
 
-    for resource in resources {
-        let mut service_name: String = "".to_string();
-        let mut service_port: i32 = 80;
-        let error_message = format!("failed to load config for {}", resource);
-
-        match resource {
-            SugarfungeResource::Api => {
-                service_name = config.api.clone().expect(&error_message).name.to_string();
-                service_port = get_service_port(client.clone(), namespace, &service_name).await;
-            }
-            SugarfungeResource::Explorer => {
-                service_name = config
-                    .explorer
-                    .clone()
-                    .expect(&error_message)
-                    .name
-                    .to_string();
-                service_port = get_service_port(client.clone(), namespace, &service_name).await;
-            }
-            SugarfungeResource::Ipfs => {
-                service_name = config.ipfs.clone().expect(&error_message).name.to_string();
-                service_port = get_service_port(client.clone(), namespace, &service_name).await;
-            }
-            SugarfungeResource::Keycloak => {
-                service_name = config
-                    .keycloak
-                    .clone()
-                    .expect(&error_message)
-                    .name
-                    .to_string();
-                service_port = get_service_port(client.clone(), namespace, &service_name).await;
-            }
-            SugarfungeResource::Node => {
-                service_name = config.node.clone().expect(&error_message).name.to_string();
-                service_port = get_service_port(client.clone(), namespace, &service_name).await;
-            }
-            SugarfungeResource::Status => {
-                service_name = config
-                    .status
-                    .clone()
-                    .expect(&error_message)
-                    .name
-                    .to_string();
-                service_port = get_service_port(client.clone(), namespace, &service_name).await;
-            }
-            _ => {}
-        }
-
+    for (service_name, service_port) in backends {
         let path = HTTPIngressPath {
             backend: IngressBackend {
                 service: Some(IngressServiceBackend {
@@ -113,8 +64,7 @@ pub async fn create(
             path_type: "Prefix".to_string(),
         };
 
-        let service_name_parsed_as_host =
-            service_name + "." + &ingress_config.host;
+        let service_name_parsed_as_host = service_name + "." + &ingress_config.host;
 
         let rule = IngressRule {
             host: Some(service_name_parsed_as_host.to_string()),
@@ -130,13 +80,10 @@ pub async fn create(
         secret_name: Some(ingress_config.tls_secret.to_string()),
     };
 
-    let ingress = Ingress {
+    Ingress {
         metadata: ObjectMeta {
             name: Some(ingress_config.name.to_string()),
-            labels: Some(BTreeMap::from([(
-                "app.kubernetes.io/name".to_string(),
-                ingress_config.name.to_string(),
-            )])),
+            labels: Some(labels(&ingress_config.name)),
             annotations: Some(BTreeMap::from([(
                 "cert-manager.io/cluster-issuer".to_string(),
                 ingress_config.tls_issuer.to_string(),
@@ -149,12 +96,85 @@ pub async fn create(
             tls: Some(vec![tls]),
             ..Default::default()
         }),
-        status: Some(IngressStatus::default()),
-    };
+        status: None,
+    }
+}
+
+/// Names and ports of the services of every instance of the resource in the config.
+fn backends(config: &Config, resource: SugarfungeResource) -> Vec<(String, i32)> {
+    match resource {
+        SugarfungeResource::Api => config
+            .api
+            .iter()
+            .map(|x| (x.name.to_string(), x.port))
+            .collect(),
+        SugarfungeResource::Explorer => config
+            .explorer
+            .iter()
+            .map(|x| (x.name.to_string(), x.port))
+            .collect(),
+        SugarfungeResource::Ipfs => config
+            .ipfs
+            .iter()
+            .map(|x| (x.name.to_string(), x.api_port))
+            .collect(),
+        SugarfungeResource::Keycloak => config
+            .keycloak
+            .iter()
+            .map(|x| (x.name.to_string(), x.port))
+            .collect(),
+        SugarfungeResource::Node => config
+            .node
+            .iter()
+            .map(|x| (x.name.to_string(), x.ws_port))
+            .collect(),
+        SugarfungeResource::Status => config
+            .status
+            .iter()
+            .map(|x| (x.name.to_string(), x.port))
+            .collect(),
+        SugarfungeResource::Ingress | SugarfungeResource::Registry => vec![],
+    }
+}
+
+/// Builds the ingress from the service ports in the config instead of the live services, with a
+/// route for every instance.
+pub fn manifests(config: &Config, resources: Vec<SugarfungeResource>) -> Vec<K8sObject> {
+    let ingress_error = format!("failed to load config for {}", SugarfungeResource::Ingress);
+    let ingress_config = config.ingress.clone().expect(&ingress_error);
+    let backends: Vec<(String, i32)> = resources
+        .into_iter()
+        .flat_map(|resource| backends(config, resource))
+        .collect();
+
+    vec![K8sObject::Ingress(ingress(&ingress_config, backends))]
+}
+
+pub async fn create(
+    client: Client,
+    namespace: &str,
+    config: Config,
+    resources: Vec<SugarfungeResource>,
+    keep_on_failure: bool,
+) -> anyhow::Result<()> {
+    let ingress_error = format!("failed to load config for {}", SugarfungeResource::Ingress);
+    let ingress_config = config.ingress.clone().expect(&ingress_error);
+    let mut backends: Vec<(String, i32)> = vec![];
+
+    for resource in resources {
+        if resource == SugarfungeResource::Ingress {
+            continue;
+        }
+
+        for service_name in names(&config, resource) {
+            let service_port = get_service_port(client.clone(), namespace, &service_name).await;
+            backends.push((service_name, service_port));
+        }
+    }
+
+    let ingress = ingress(&ingress_config, backends);
+
+    let objects = with_stack_metadata(&config, vec![K8sObject::Ingress(ingress)]);
 
-    let pp = PostParams::default();
-    ingress_res
-        .create(&pp, &ingress)
-        .await
-        .map_err(|e| e.into())
+    create_resources(client, namespace, objects, keep_on_failure).await
 }
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec, DeploymentStatus},
+        apps::v1::{Deployment, DeploymentSpec},
         core::v1::{
             ConfigMapVolumeSource, Container, ContainerPort, EmptyDirVolumeSource, PodSpec,
             PodTemplateSpec, Probe, SecretVolumeSource, ServicePort, TCPSocketAction, Volume,
@@ -11,14 +11,14 @@ use k8s_openapi::{
     },
     apimachinery::pkg::{apis::meta::v1::LabelSelector, util::intstr::IntOrString},
 };
-use kube::{
-    api::{ObjectMeta, PostParams},
-    Api, Client,
-};
+use kube::api::ObjectMeta;
 
 use crate::{
     config::IpfsConfig,
-    utils::{create_configmap, create_secret, create_service, ServiceData},
+    utils::{
+        configmap, image_pull_secrets, labels, pod_scheduling, resource_requirements, secret,
+        service, with_pod_extras, K8sObject, ServiceData,
+    },
 };
 
 const CONFIG_FILE: &str = r#"
@@ -93,6 +93,7 @@ fn init_container(config: IpfsConfig) -> Container {
         ]),
         name: "configure-".to_string() + &config.name,
         volume_mounts: Some(volume_mounts),
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
@@ -138,21 +139,19 @@ fn container(config: IpfsConfig) -> Container {
             period_seconds: Some(15),
             ..Default::default()
         }),
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
 
-pub async fn deployment(namespace: &str, config: IpfsConfig) -> anyhow::Result<Deployment> {
-    let client = Client::try_default().await?;
+pub fn manifests(config: &IpfsConfig) -> Vec<K8sObject> {
+    let mut objects: Vec<K8sObject> = vec![];
 
     let mut volumes: Vec<Volume> = vec![];
 
     let metadata = ObjectMeta {
         name: Some(config.name.to_string()),
-        labels: Some(BTreeMap::from([(
-            "app.kubernetes.io/name".to_string(),
-            config.name.to_string(),
-        )])),
+        labels: Some(labels(&config.name)),
         ..Default::default()
     };
 
@@ -168,15 +167,13 @@ pub async fn deployment(namespace: &str, config: IpfsConfig) -> anyhow::Result<D
         ..Default::default()
     };
 
-    let _service =
-        create_service(client.clone(), namespace, metadata.clone(), service_data).await?;
+    objects.push(K8sObject::Service(service(metadata.clone(), service_data)));
 
     if let Some(ref swarm_key) = config.swarm_key {
         let key_file = "/key/swarm/psk/1.0.0/\n/base16/\n".to_string() + swarm_key;
         let secret_data = BTreeMap::from([("swarm.key".to_string(), key_file)]);
 
-        let _secret =
-            create_secret(client.clone(), namespace, metadata.clone(), secret_data).await?;
+        objects.push(K8sObject::Secret(secret(metadata.clone(), secret_data)));
 
         let swarm_key_volume = Volume {
             name: config.name.to_string() + "-swarm",
@@ -193,10 +190,10 @@ pub async fn deployment(namespace: &str, config: IpfsConfig) -> anyhow::Result<D
     let configmap_data =
         BTreeMap::from([("configure-ipfs.sh".to_string(), CONFIG_FILE.to_string())]);
 
-    let _configmap =
-        create_configmap(client.clone(), namespace, metadata.clone(), configmap_data).await?;
-
-    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    objects.push(K8sObject::ConfigMap(configmap(
+        metadata.clone(),
+        configmap_data,
+    )));
 
     let init_container = init_container(config.clone());
 
@@ -226,12 +223,16 @@ pub async fn deployment(namespace: &str, config: IpfsConfig) -> anyhow::Result<D
         spec: Some(DeploymentSpec {
             template: PodTemplateSpec {
                 metadata: Some(metadata.clone()),
-                spec: Some(PodSpec {
-                    init_containers: Some(vec![init_container]),
-                    containers: vec![container],
-                    volumes: Some(volumes),
-                    ..Default::default()
-                }),
+                spec: Some(with_pod_extras(
+                    PodSpec {
+                        init_containers: Some(vec![init_container]),
+                        containers: vec![container],
+                        volumes: Some(volumes),
+                        image_pull_secrets: image_pull_secrets(&config.pod.image_pull_secrets),
+                        ..pod_scheduling(&config.pod.scheduling)
+                    },
+                    &config.pod,
+                )),
             },
             selector: LabelSelector {
                 match_labels: Some(BTreeMap::from([(
@@ -242,9 +243,10 @@ pub async fn deployment(namespace: &str, config: IpfsConfig) -> anyhow::Result<D
             },
             ..Default::default()
         }),
-        status: Some(DeploymentStatus::default()),
+        status: None,
     };
 
-    let pp = PostParams::default();
-    deployments.create(&pp, &api).await.map_err(|e| e.into())
+    objects.push(K8sObject::Deployment(api));
+
+    objects
 }
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec, DeploymentStatus},
+        apps::v1::{Deployment, DeploymentSpec},
         core::v1::{
             ConfigMapEnvSource, Container, ContainerPort, EnvFromSource, HTTPGetAction, PodSpec,
             PodTemplateSpec, Probe, SecretEnvSource, ServicePort,
@@ -11,16 +11,15 @@ use k8s_openapi::{
     apimachinery::pkg::{apis::meta::v1::LabelSelector, util::intstr::IntOrString},
 };
 
-use kube::{
-    api::{Api, PostParams},
-    core::ObjectMeta,
-    Client,
-};
+use kube::core::ObjectMeta;
 
 use crate::{
     config::KeycloakConfig,
     utils::ServiceData,
-    utils::{create_configmap, create_secret, create_service},
+    utils::{
+        configmap, image_pull_secrets, labels, pod_scheduling, resource_requirements, secret,
+        service, with_pod_extras, K8sObject,
+    },
 };
 
 fn container(config: KeycloakConfig) -> Container {
@@ -72,19 +71,15 @@ fn container(config: KeycloakConfig) -> Container {
             timeout_seconds: Some(5),
             ..Default::default()
         }),
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
 
-pub async fn deployment(namespace: &str, config: KeycloakConfig) -> anyhow::Result<Deployment> {
-    let client = Client::try_default().await?;
-
+pub fn manifests(config: &KeycloakConfig) -> Vec<K8sObject> {
     let metadata = ObjectMeta {
         name: Some(config.name.to_string()),
-        labels: Some(BTreeMap::from([(
-            "app.kubernetes.io/name".to_string(),
-            config.name.to_string(),
-        )])),
+        labels: Some(labels(&config.name)),
         ..Default::default()
     };
 
@@ -100,8 +95,7 @@ pub async fn deployment(namespace: &str, config: KeycloakConfig) -> anyhow::Resu
         ..Default::default()
     };
 
-    let _service =
-        create_service(client.clone(), namespace, metadata.clone(), service_data).await?;
+    let service = service(metadata.clone(), service_data);
 
     let secret_data = BTreeMap::from([
         (
@@ -122,7 +116,7 @@ pub async fn deployment(namespace: &str, config: KeycloakConfig) -> anyhow::Resu
         ),
     ]);
 
-    let _secret = create_secret(client.clone(), namespace, metadata.clone(), secret_data).await?;
+    let secret = secret(metadata.clone(), secret_data);
 
     let configmap_data = BTreeMap::from([
         ("KC_DB".to_string(), "postgres".to_string()),
@@ -145,10 +139,7 @@ pub async fn deployment(namespace: &str, config: KeycloakConfig) -> anyhow::Resu
         ),
     ]);
 
-    let _configmap =
-        create_configmap(client.clone(), namespace, metadata.clone(), configmap_data).await?;
-
-    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let configmap = configmap(metadata.clone(), configmap_data);
 
     let container = container(config.clone());
 
@@ -157,10 +148,14 @@ pub async fn deployment(namespace: &str, config: KeycloakConfig) -> anyhow::Resu
         spec: Some(DeploymentSpec {
             template: PodTemplateSpec {
                 metadata: Some(metadata.clone()),
-                spec: Some(PodSpec {
-                    containers: vec![container],
-                    ..Default::default()
-                }),
+                spec: Some(with_pod_extras(
+                    PodSpec {
+                        containers: vec![container],
+                        image_pull_secrets: image_pull_secrets(&config.pod.image_pull_secrets),
+                        ..pod_scheduling(&config.pod.scheduling)
+                    },
+                    &config.pod,
+                )),
             },
             selector: LabelSelector {
                 match_labels: Some(BTreeMap::from([(
@@ -171,12 +166,13 @@ pub async fn deployment(namespace: &str, config: KeycloakConfig) -> anyhow::Resu
             },
             ..Default::default()
         }),
-        status: Some(DeploymentStatus::default()),
+        status: None,
     };
 
-    let pp = PostParams::default();
-    deployments
-        .create(&pp, &keycloak)
-        .await
-        .map_err(|e| e.into())
+    vec![
+        K8sObject::Service(service),
+        K8sObject::Secret(secret),
+        K8sObject::ConfigMap(configmap),
+        K8sObject::Deployment(keycloak),
+    ]
 }
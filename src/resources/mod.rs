@@ -1,7 +1,287 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+    api::core::v1::LocalObjectReference, apimachinery::pkg::apis::meta::v1::LabelSelector,
+};
+
+use crate::{
+    config::{select, Config, Instance, StackConfig},
+    utils::{K8sObject, STACK_LABEL},
+    SugarfungeChainType, SugarfungeResource,
+};
+
 pub mod api;
 pub mod explorer;
 pub mod ingress;
 pub mod ipfs;
 pub mod keycloak;
 pub mod node;
+pub mod registry;
 pub mod status;
+
+/// Services exposed through the ingress.
+pub const SERVICES: [SugarfungeResource; 6] = [
+    SugarfungeResource::Api,
+    SugarfungeResource::Explorer,
+    SugarfungeResource::Ipfs,
+    SugarfungeResource::Keycloak,
+    SugarfungeResource::Node,
+    SugarfungeResource::Status,
+];
+
+/// Every resource in dependency order: the pull secret first, the node before the services using
+/// it and the ingress last.
+pub const RESOURCES: [SugarfungeResource; 8] = [
+    SugarfungeResource::Registry,
+    SugarfungeResource::Node,
+    SugarfungeResource::Ipfs,
+    SugarfungeResource::Keycloak,
+    SugarfungeResource::Api,
+    SugarfungeResource::Explorer,
+    SugarfungeResource::Status,
+    SugarfungeResource::Ingress,
+];
+
+/// Label naming the component of a pod, shared by the pods of its instances.
+const COMPONENT_LABEL: &str = "app.kubernetes.io/component";
+
+/// Labels telling the objects of the stack apart from the ones of other stacks: the labels of the
+/// stack and its prefix.
+pub fn stack_labels(stack: Option<&StackConfig>) -> BTreeMap<String, String> {
+    let stack = match stack {
+        Some(stack) => stack,
+        None => return BTreeMap::new(),
+    };
+
+    let mut labels = stack.labels.clone().unwrap_or_default();
+
+    // A label value starts and ends with an alphanumeric character, unlike a prefix like `demo-`.
+    if let Some(prefix) = &stack.prefix {
+        let value = prefix.trim_matches(|x: char| !x.is_ascii_alphanumeric());
+        if !value.is_empty() {
+            labels
+                .entry(STACK_LABEL.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+
+    labels
+}
+
+/// Labels shared by the pods of every instance of the component in the stack.
+pub fn component_labels(
+    resource: SugarfungeResource,
+    stack: Option<&StackConfig>,
+) -> BTreeMap<String, String> {
+    let mut labels = stack_labels(stack);
+    labels.insert(
+        COMPONENT_LABEL.to_string(),
+        resource.to_string().to_lowercase(),
+    );
+    labels
+}
+
+/// Adds the component labels to the pods, and spreads the pods of every instance of the component
+/// with the topology spread constraints without a label selector.
+pub fn with_component_labels(
+    config: &Config,
+    resource: SugarfungeResource,
+    mut objects: Vec<K8sObject>,
+) -> Vec<K8sObject> {
+    let labels = component_labels(resource, config.stack.as_ref());
+
+    for object in &mut objects {
+        if let Some(template) = object.pod_template_mut() {
+            let pod_labels = template
+                .metadata
+                .get_or_insert_with(Default::default)
+                .labels
+                .get_or_insert_with(BTreeMap::new);
+            for (key, value) in &labels {
+                pod_labels
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+
+            let constraints = template
+                .spec
+                .as_mut()
+                .and_then(|x| x.topology_spread_constraints.as_mut());
+            for constraint in constraints.into_iter().flatten() {
+                constraint
+                    .label_selector
+                    .get_or_insert_with(|| LabelSelector {
+                        match_labels: Some(labels.clone()),
+                        ..Default::default()
+                    });
+            }
+        }
+    }
+
+    objects
+}
+
+/// The objects of one instance of a resource.
+pub struct Manifest {
+    pub resource: SugarfungeResource,
+    pub name: String,
+    pub objects: Vec<K8sObject>,
+}
+
+fn instance_names<T: Instance>(instances: &[T]) -> Vec<String> {
+    instances.iter().map(|x| x.name().to_string()).collect()
+}
+
+/// Names of the instances of the resource, each one naming the objects of the instance.
+pub fn names(config: &Config, resource: SugarfungeResource) -> Vec<String> {
+    match resource {
+        SugarfungeResource::Api => instance_names(&config.api),
+        SugarfungeResource::Explorer => instance_names(&config.explorer),
+        SugarfungeResource::Ipfs => instance_names(&config.ipfs),
+        SugarfungeResource::Keycloak => instance_names(&config.keycloak),
+        SugarfungeResource::Node => instance_names(&config.node),
+        SugarfungeResource::Status => instance_names(&config.status),
+        SugarfungeResource::Ingress => config.ingress.iter().map(|x| x.name.to_string()).collect(),
+        SugarfungeResource::Registry => {
+            config.registry.iter().map(|x| x.name.to_string()).collect()
+        }
+    }
+}
+
+pub fn is_configured(config: &Config, resource: SugarfungeResource) -> bool {
+    !names(config, resource).is_empty()
+}
+
+/// Adds the common labels and annotations of the stack to the objects.
+pub fn with_stack_metadata(config: &Config, mut objects: Vec<K8sObject>) -> Vec<K8sObject> {
+    if let Some(stack) = &config.stack {
+        let labels = stack_labels(Some(stack));
+        let annotations = stack.annotations.clone().unwrap_or_default();
+
+        for object in &mut objects {
+            object.add_metadata(&labels, &annotations);
+        }
+    }
+
+    objects
+}
+
+/// Pods without their own `image_pull_secrets` pull with the registry secret.
+pub fn with_pull_secrets(config: &Config, mut objects: Vec<K8sObject>) -> Vec<K8sObject> {
+    if let Some(registry) = &config.registry {
+        for object in &mut objects {
+            if let Some(spec) = object.pod_template_mut().and_then(|x| x.spec.as_mut()) {
+                spec.image_pull_secrets.get_or_insert_with(|| {
+                    vec![LocalObjectReference {
+                        name: Some(registry.name.to_string()),
+                    }]
+                });
+            }
+        }
+    }
+
+    objects
+}
+
+fn build<'a, T: Instance + 'a>(
+    instances: impl IntoIterator<Item = &'a T>,
+    name: Option<&str>,
+    manifests: impl Fn(&T) -> Vec<K8sObject>,
+) -> Vec<(String, Vec<K8sObject>)> {
+    select(instances, name)
+        .into_iter()
+        .map(|x| (x.name().to_string(), manifests(x)))
+        .collect()
+}
+
+/// Builds the objects of every instance of the given resources, or only of the instance with the
+/// given name.
+pub fn manifests(
+    config: &Config,
+    chain: SugarfungeChainType,
+    resources: &[SugarfungeResource],
+    name: Option<&str>,
+) -> Vec<Manifest> {
+    let mut manifests = vec![];
+
+    for resource in resources {
+        let instances = match resource {
+            SugarfungeResource::Api => build(&config.api, name, api::manifests),
+            SugarfungeResource::Explorer => build(&config.explorer, name, explorer::manifests),
+            SugarfungeResource::Ipfs => build(&config.ipfs, name, ipfs::manifests),
+            SugarfungeResource::Keycloak => build(&config.keycloak, name, keycloak::manifests),
+            SugarfungeResource::Node => build(&config.node, name, |x| {
+                node::manifests(chain, x, &config.node, config.stack.as_ref())
+            }),
+            SugarfungeResource::Status => build(&config.status, name, status::manifests),
+            SugarfungeResource::Registry => build(&config.registry, name, registry::manifests),
+            SugarfungeResource::Ingress => select(&config.ingress, name)
+                .into_iter()
+                .map(|x| {
+                    let objects = ingress::manifests(config, SERVICES.to_vec());
+                    (x.name.to_string(), objects)
+                })
+                .collect(),
+        };
+
+        for (name, objects) in instances {
+            manifests.push(Manifest {
+                resource: *resource,
+                name,
+                objects: with_pull_secrets(
+                    config,
+                    with_stack_metadata(config, with_component_labels(config, *resource, objects)),
+                ),
+            });
+        }
+    }
+
+    manifests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigFormat;
+
+    #[test]
+    fn topology_spread_selects_the_pods_of_every_node() {
+        let layer = ConfigFormat::Yaml
+            .parse(
+                "stack: {prefix: team-a-}
+node:
+  - name: sf-node-1
+    pod:
+      scheduling:
+        topology_spread:
+          - {maxSkew: 1, topologyKey: topology.kubernetes.io/zone, whenUnsatisfiable: ScheduleAnyway}
+  - name: sf-node-2
+",
+            )
+            .unwrap();
+        let config = Config::from_layers(vec![layer]).unwrap();
+
+        let mut pods = manifests(
+            &config,
+            SugarfungeChainType::Local,
+            &[SugarfungeResource::Node],
+            None,
+        )
+        .into_iter()
+        .flat_map(|x| x.objects)
+        .filter_map(|mut x| x.pod_template_mut().cloned());
+        let (first, second) = (pods.next().unwrap(), pods.next().unwrap());
+
+        let constraints = first.spec.unwrap().topology_spread_constraints.unwrap();
+        let selector = constraints[0].label_selector.clone().unwrap();
+        let match_labels = selector.match_labels.unwrap();
+        assert_eq!(match_labels["app.kubernetes.io/part-of"], "team-a");
+
+        for pod in [first.metadata, second.metadata] {
+            let labels = pod.unwrap().labels.unwrap();
+            for (key, value) in &match_labels {
+                assert_eq!(labels.get(key), Some(value));
+            }
+        }
+    }
+}
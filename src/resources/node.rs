@@ -1,29 +1,58 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::Ipv4Addr};
 
 use k8s_openapi::{
     api::{
-        apps::v1::{StatefulSet, StatefulSetSpec, StatefulSetStatus},
+        apps::v1::{StatefulSet, StatefulSetSpec},
         core::v1::{
-            ConfigMapEnvSource, Container, ContainerPort, EmptyDirVolumeSource, EnvFromSource,
-            PodSecurityContext, PodSpec, PodTemplateSpec, Secret, SecretVolumeSource, ServicePort,
-            Volume, VolumeMount,
+            Affinity, ConfigMapEnvSource, Container, ContainerPort, EmptyDirVolumeSource,
+            EnvFromSource, EnvVar, EnvVarSource, PersistentVolumeClaim, PersistentVolumeClaimSpec,
+            PodAffinityTerm, PodAntiAffinity, PodSecurityContext, PodSpec, PodTemplateSpec,
+            ResourceRequirements, Secret, SecretKeySelector, SecretVolumeSource, Service,
+            ServicePort, ServiceSpec, Volume, VolumeMount, WeightedPodAffinityTerm,
         },
     },
-    apimachinery::pkg::{apis::meta::v1::LabelSelector, util::intstr::IntOrString},
+    apimachinery::pkg::{
+        api::resource::Quantity, apis::meta::v1::LabelSelector, util::intstr::IntOrString,
+    },
 };
 
-use kube::{
-    api::{Api, PostParams},
-    core::ObjectMeta,
-    Client,
-};
+use kube::{api::Api, core::ObjectMeta, Client};
 
 use crate::{
-    config::NodeConfig,
-    utils::{create_configmap, create_service, ServiceData},
-    SugarfungeChainType,
+    config::{NodeConfig, P2pServiceConfig, P2pServiceType, RpcConfig, RpcMethods, StackConfig},
+    keys,
+    resources::component_labels,
+    utils::{
+        configmap, image_pull_secrets, labels, pod_scheduling, resource_requirements, secret,
+        service, with_pod_extras, K8sObject, ServiceData,
+    },
+    SugarfungeChainType, SugarfungeResource,
 };
 
+/// Prefers to run each node pod on a different host than the other node pods of the stack, so a
+/// lost host takes down a single node.
+fn default_affinity(stack: Option<&StackConfig>) -> Affinity {
+    Affinity {
+        pod_anti_affinity: Some(PodAntiAffinity {
+            preferred_during_scheduling_ignored_during_execution: Some(vec![
+                WeightedPodAffinityTerm {
+                    weight: 100,
+                    pod_affinity_term: PodAffinityTerm {
+                        label_selector: Some(LabelSelector {
+                            match_labels: Some(component_labels(SugarfungeResource::Node, stack)),
+                            ..Default::default()
+                        }),
+                        topology_key: "kubernetes.io/hostname".to_string(),
+                        ..Default::default()
+                    },
+                },
+            ]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
 fn init_container(config: &NodeConfig) -> Container {
     let volume_mount = VolumeMount {
         name: config.name.to_string() + "-config",
@@ -54,11 +83,209 @@ fn init_container(config: &NodeConfig) -> Container {
                 .to_string(),
         ]),
         volume_mounts: Some(vec![volume_mount]),
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
 
-fn container(chain_type: SugarfungeChainType, config: NodeConfig) -> Container {
+/// The `--chain` argument and the chainspec mount of the testnet node, `None` for the local chain
+/// built into the node.
+fn chainspec(
+    chain_type: SugarfungeChainType,
+    config: &NodeConfig,
+) -> Option<(String, VolumeMount)> {
+    if chain_type != SugarfungeChainType::Testnet {
+        return None;
+    }
+
+    let mut chainspec_file_name = "customSpec.json".to_string();
+
+    if let Some(ref file_name) = config.chainspec_file_name {
+        chainspec_file_name = file_name.to_string();
+    }
+
+    let volume_mount = VolumeMount {
+        name: config.name.to_owned() + "-config",
+        mount_path: "/chainspec/".to_string() + &chainspec_file_name,
+        sub_path: Some(chainspec_file_name.to_string()),
+        ..Default::default()
+    };
+
+    Some((
+        "--chain=/chainspec/".to_string() + &chainspec_file_name,
+        volume_mount,
+    ))
+}
+
+/// Name of the secret holding the session keys of the validator.
+pub fn session_keys_secret_name(name: &str) -> String {
+    name.to_string() + "-session-keys"
+}
+
+/// Inserts a session key into the keystore in the data volume, with the secret URI read from
+/// the session keys secret so it is not written in the pod spec.
+fn key_insert_container(
+    chain_type: SugarfungeChainType,
+    config: &NodeConfig,
+    key_type: &str,
+    scheme: &str,
+) -> Container {
+    let storage = config.storage.clone().unwrap_or_default();
+
+    let suri = EnvVar {
+        name: "SURI".to_string(),
+        value_from: Some(EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                name: Some(session_keys_secret_name(&config.name)),
+                key: key_type.to_string(),
+                optional: Some(false),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let mut args = vec![
+        "key".to_string(),
+        "insert".to_string(),
+        "--base-path=".to_string() + &storage.mount_path,
+        "--key-type=".to_string() + key_type,
+        "--scheme=".to_string() + scheme,
+        "--suri=$(SURI)".to_string(),
+    ];
+
+    let mut volume_mounts = vec![VolumeMount {
+        name: config.name.to_string() + "-data",
+        mount_path: storage.mount_path.to_string(),
+        ..Default::default()
+    }];
+
+    // The keystore is a directory of the chain, so the key is inserted for the same chain.
+    if let Some((chain_arg, chainspec_mount)) = chainspec(chain_type, config) {
+        args.push(chain_arg);
+        volume_mounts.push(chainspec_mount);
+    }
+
+    Container {
+        name: format!("{}-{}-key", config.name, key_type),
+        image: Some(config.image.to_string()),
+        image_pull_policy: Some("IfNotPresent".to_string()),
+        args: Some(args),
+        env: Some(vec![suri]),
+        volume_mounts: Some(volume_mounts),
+        resources: resource_requirements(&config.pod.resources),
+        ..Default::default()
+    }
+}
+
+fn rpc_args(rpc: &RpcConfig) -> Vec<String> {
+    let mut args = vec!["--rpc-methods=".to_string() + &rpc.methods.to_string()];
+
+    // The node refuses to expose the unsafe methods without the explicit flags.
+    let unsafe_prefix = match rpc.methods {
+        RpcMethods::Unsafe => "--unsafe-",
+        _ => "--",
+    };
+
+    if rpc.ws_external {
+        args.push(unsafe_prefix.to_string() + "ws-external");
+    }
+
+    if rpc.rpc_external {
+        args.push(unsafe_prefix.to_string() + "rpc-external");
+    }
+
+    if let Some(ref cors) = rpc.cors {
+        args.push("--rpc-cors=".to_string() + &cors.join(","));
+    }
+
+    if let Some(max_connections) = rpc.max_connections {
+        args.push("--ws-max-connections=".to_string() + &max_connections.to_string());
+    }
+
+    args
+}
+
+/// Name of the service exposing the P2P port of the instance outside the cluster.
+pub fn p2p_service_name(name: &str) -> String {
+    name.to_string() + "-p2p"
+}
+
+/// The address of the P2P service announced to the peers, when its host and port are known.
+fn public_addr(config: &NodeConfig) -> Option<String> {
+    let p2p_service = config.p2p_service.as_ref()?;
+    let public_host = p2p_service.public_host.as_ref()?;
+
+    let port = match p2p_service.service_type {
+        P2pServiceType::NodePort => p2p_service.node_port?,
+        P2pServiceType::LoadBalancer => config.p2p_port,
+    };
+
+    let protocol = match public_host.parse::<Ipv4Addr>() {
+        Ok(_) => "ip4",
+        Err(_) => "dns4",
+    };
+
+    Some(format!("/{}/{}/tcp/{}", protocol, public_host, port))
+}
+
+/// Service of the single pod of the StatefulSet, so peers reaching it always reach this node.
+fn p2p_service(
+    metadata: &ObjectMeta,
+    config: &NodeConfig,
+    p2p_service: &P2pServiceConfig,
+) -> Service {
+    Service {
+        metadata: ObjectMeta {
+            name: Some(p2p_service_name(&config.name)),
+            ..metadata.clone()
+        },
+        spec: Some(ServiceSpec {
+            type_: Some(p2p_service.service_type.to_string()),
+            ports: Some(vec![ServicePort {
+                name: Some("p2p".to_string()),
+                protocol: Some("TCP".to_string()),
+                port: config.p2p_port,
+                target_port: Some(IntOrString::Int(config.p2p_port)),
+                node_port: p2p_service.node_port,
+                ..Default::default()
+            }]),
+            selector: Some(BTreeMap::from([(
+                "statefulset.kubernetes.io/pod-name".to_string(),
+                config.name.to_string() + "-0",
+            )])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Name of the secret holding the node key of the instance.
+pub fn node_key_secret_name(name: &str) -> String {
+    name.to_string() + "-node-key"
+}
+
+/// Multiaddrs of the other node instances with a node key, through the stable DNS name of their
+/// pod in their headless service.
+fn bootnodes(config: &NodeConfig, nodes: &[NodeConfig]) -> Vec<String> {
+    nodes
+        .iter()
+        .filter(|x| x.name != config.name)
+        .filter_map(|x| {
+            let peer_id = keys::peer_id(x.node_key.as_ref()?).ok()?;
+            Some(format!(
+                "/dns4/{0}-0.{0}/tcp/{1}/p2p/{2}",
+                x.name, x.p2p_port, peer_id
+            ))
+        })
+        .collect()
+}
+
+fn container(
+    chain_type: SugarfungeChainType,
+    config: NodeConfig,
+    bootnodes: Vec<String>,
+) -> Container {
     let env = EnvFromSource {
         config_map_ref: Some(ConfigMapEnvSource {
             name: Some(config.name.to_string()),
@@ -67,18 +294,68 @@ fn container(chain_type: SugarfungeChainType, config: NodeConfig) -> Container {
         ..Default::default()
     };
 
+    let chainspec = chainspec(chain_type, &config);
+
+    // The development account flags like `--alice` also insert the keys of the account, so a
+    // validator with its own session keys is only given the name.
+    let name = match config.session_keys {
+        Some(_) => "--name=".to_string() + &config.node_name,
+        None => "--".to_string() + &config.node_name,
+    };
+
     let mut args = vec![
-        "--".to_string() + &config.node_name,
+        name,
         "--port=".to_string() + &config.p2p_port.to_string(),
         "--ws-port=".to_string() + &config.ws_port.to_string(),
+        "--rpc-port=".to_string() + &config.rpc_port.to_string(),
         "--prometheus-port=".to_string() + &config.prometheus_port.to_string(),
-        "--unsafe-ws-external".to_string(),
-        "--unsafe-rpc-external".to_string(),
-        "--rpc-methods=Unsafe".to_string(),
-        "--rpc-cors=all".to_string(),
         "--prometheus-external".to_string(),
     ];
 
+    args.extend(rpc_args(
+        &config
+            .rpc
+            .clone()
+            .unwrap_or_else(|| RpcConfig::for_chain(chain_type)),
+    ));
+
+    let mut volume_mounts: Option<Vec<VolumeMount>> = None;
+
+    if let Some(ref storage) = config.storage {
+        volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(VolumeMount {
+                name: config.name.to_string() + "-data",
+                mount_path: storage.mount_path.to_string(),
+                ..Default::default()
+            });
+        args.push("--base-path=".to_string() + &storage.mount_path);
+    }
+
+    if config.node_key.is_some() {
+        volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(VolumeMount {
+                name: config.name.to_string() + "-node-key",
+                mount_path: "/keys/node".to_string(),
+                read_only: Some(true),
+                ..Default::default()
+            });
+        args.push("--node-key-file=/keys/node/node-key".to_string());
+    }
+
+    if config.session_keys.is_some() {
+        args.push("--validator".to_string());
+    }
+
+    if let Some(public_addr) = public_addr(&config) {
+        args.push("--public-addr=".to_string() + &public_addr);
+    }
+
+    for bootnode in bootnodes {
+        args.push("--bootnodes=".to_string() + &bootnode);
+    }
+
     if let Some(bootnode) = config.bootnode {
         if let Some(dns_url) = bootnode.dns_url {
             args.push(format!(
@@ -104,6 +381,12 @@ fn container(chain_type: SugarfungeChainType, config: NodeConfig) -> Container {
         ..Default::default()
     };
 
+    let http_rpc_container_port = ContainerPort {
+        name: Some("http-rpc-port".to_string()),
+        container_port: config.rpc_port,
+        ..Default::default()
+    };
+
     let p2p_container_port = ContainerPort {
         name: Some("p2p-port".to_string()),
         container_port: config.p2p_port,
@@ -116,22 +399,11 @@ fn container(chain_type: SugarfungeChainType, config: NodeConfig) -> Container {
         ..Default::default()
     };
 
-    let mut volume_mounts: Option<Vec<VolumeMount>> = None;
-
-    if chain_type == SugarfungeChainType::Testnet {
-        let mut chainspec_file_name = "customSpec.json".to_string();
-
-        if let Some(file_name) = config.chainspec_file_name {
-            chainspec_file_name = file_name.to_string();
-        }
-
-        volume_mounts = Some(vec![VolumeMount {
-            name: config.name.to_owned() + "-config",
-            mount_path: "/chainspec/".to_string() + &chainspec_file_name,
-            sub_path: Some(chainspec_file_name.to_string()),
-            ..Default::default()
-        }]);
-        args.push("--chain=/chainspec/".to_string() + &chainspec_file_name);
+    if let Some((chain_arg, chainspec_mount)) = chainspec {
+        volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(chainspec_mount);
+        args.push(chain_arg);
     }
 
     Container {
@@ -141,28 +413,26 @@ fn container(chain_type: SugarfungeChainType, config: NodeConfig) -> Container {
         name: config.name.to_string(),
         ports: Some(vec![
             ws_container_port,
+            http_rpc_container_port,
             p2p_container_port,
             prometheus_container_port,
         ]),
         args: Some(args),
         volume_mounts,
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
 
-pub async fn statefulset(
-    namespace: &str,
+pub fn manifests(
     chain_type: SugarfungeChainType,
-    config: NodeConfig,
-) -> anyhow::Result<StatefulSet> {
-    let client = Client::try_default().await?;
-
+    config: &NodeConfig,
+    nodes: &[NodeConfig],
+    stack: Option<&StackConfig>,
+) -> Vec<K8sObject> {
     let metadata = ObjectMeta {
         name: Some(config.name.to_string()),
-        labels: Some(BTreeMap::from([(
-            "app.kubernetes.io/name".to_string(),
-            config.name.to_string(),
-        )])),
+        labels: Some(labels(&config.name)),
         ..Default::default()
     };
 
@@ -172,38 +442,33 @@ pub async fn statefulset(
 
     if chain_type == SugarfungeChainType::Testnet {
         // Check if the chainspec comes from an external url using the config file.
-        // Otherwise check if a secret was created without the tool that contains the chainspec.
+        // Otherwise use a secret created without the tool that contains the chainspec.
         if config.chainspec_ext.is_some() {
-            volumes = Some(vec![Volume {
+            volumes.get_or_insert_with(Vec::new).push(Volume {
                 name: config.name.to_string() + "-config",
                 empty_dir: Some(EmptyDirVolumeSource::default()),
                 ..Default::default()
-            }]);
+            });
 
-            init_containers = Some(vec![init_container(&config)]);
+            init_containers
+                .get_or_insert_with(Vec::new)
+                .push(init_container(config));
         } else {
-            let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
-
-            if secrets.get_opt(&config.name).await?.is_none() {
-                return Err(anyhow::Error::msg(format!(
-                    "The secret {} does not exist",
-                    config.name
-                )));
-            }
-
-            volumes = Some(vec![Volume {
+            volumes.get_or_insert_with(Vec::new).push(Volume {
                 name: config.name.to_string() + "-config",
                 secret: Some(SecretVolumeSource {
                     secret_name: Some(config.name.to_string()),
                     ..Default::default()
                 }),
                 ..Default::default()
-            }]);
+            });
         }
     }
 
+    // The ws port comes first, it is the one routed by the ingress.
     let service_data = ServiceData {
         service_port: ServicePort {
+            name: Some("ws".to_string()),
             protocol: Some("TCP".to_string()),
             port: config.ws_port,
             target_port: Some(IntOrString::Int(config.ws_port)),
@@ -214,33 +479,122 @@ pub async fn statefulset(
         ..Default::default()
     };
 
-    let _service =
-        create_service(client.clone(), namespace, metadata.clone(), service_data).await?;
+    let mut service = service(metadata.clone(), service_data);
+
+    if let Some(ports) = service.spec.as_mut().and_then(|x| x.ports.as_mut()) {
+        for (name, port) in [
+            ("http-rpc", config.rpc_port),
+            ("p2p", config.p2p_port),
+            ("prometheus", config.prometheus_port),
+        ] {
+            ports.push(ServicePort {
+                name: Some(name.to_string()),
+                protocol: Some("TCP".to_string()),
+                port,
+                target_port: Some(IntOrString::Int(port)),
+                ..Default::default()
+            });
+        }
+    }
 
     let configmap_data = BTreeMap::from([("CHAIN".to_string(), "sugarfunge".to_string())]);
 
-    let _configmap =
-        create_configmap(client.clone(), namespace, metadata.clone(), configmap_data).await?;
+    let configmap = configmap(metadata.clone(), configmap_data);
+
+    let mut node_key_secret: Option<Secret> = None;
+
+    // The chainspec secret is named after the node, so the node key has its own secret.
+    if let Some(ref node_key) = config.node_key {
+        let secret_name = node_key_secret_name(&config.name);
+        let secret_metadata = ObjectMeta {
+            name: Some(secret_name.to_string()),
+            ..metadata.clone()
+        };
+        let secret_data = BTreeMap::from([("node-key".to_string(), node_key.to_string())]);
+
+        node_key_secret = Some(secret(secret_metadata, secret_data));
+
+        volumes.get_or_insert_with(Vec::new).push(Volume {
+            name: config.name.to_string() + "-node-key",
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(secret_name),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
+    let mut session_keys_secret: Option<Secret> = None;
+
+    // Validators get their session keys inserted after the chainspec is in place.
+    if let Some(ref session_keys) = config.session_keys {
+        let secret_metadata = ObjectMeta {
+            name: Some(session_keys_secret_name(&config.name)),
+            ..metadata.clone()
+        };
+        let secret_data = BTreeMap::from([
+            ("aura".to_string(), session_keys.aura.to_string()),
+            ("gran".to_string(), session_keys.grandpa.to_string()),
+        ]);
+
+        session_keys_secret = Some(secret(secret_metadata, secret_data));
+
+        init_containers.get_or_insert_with(Vec::new).extend([
+            key_insert_container(chain_type, config, "aura", "Sr25519"),
+            key_insert_container(chain_type, config, "gran", "Ed25519"),
+        ]);
+    }
+
+    let container = container(chain_type, config.clone(), bootnodes(config, nodes));
 
-    let statefulsets: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+    // The claims are kept when the StatefulSet is deleted, so the chain survives a redeploy.
+    let volume_claim_templates = config.storage.as_ref().map(|storage| {
+        vec![PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(config.name.to_string() + "-data"),
+                labels: Some(labels(&config.name)),
+                ..Default::default()
+            },
+            spec: Some(PersistentVolumeClaimSpec {
+                access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+                storage_class_name: storage.storage_class.clone(),
+                resources: Some(ResourceRequirements {
+                    requests: Some(BTreeMap::from([(
+                        "storage".to_string(),
+                        Quantity(storage.size.to_string()),
+                    )])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]
+    });
 
-    let container = container(chain_type, config.clone());
+    let mut scheduling = config.pod.scheduling.clone().unwrap_or_default();
+    scheduling
+        .affinity
+        .get_or_insert_with(|| default_affinity(stack));
 
     let node = StatefulSet {
         metadata: metadata.clone(),
         spec: Some(StatefulSetSpec {
             template: PodTemplateSpec {
                 metadata: Some(metadata.clone()),
-                spec: Some(PodSpec {
-                    security_context: Some(PodSecurityContext {
-                        fs_group: Some(1000),
-                        ..Default::default()
-                    }),
-                    init_containers,
-                    containers: vec![container],
-                    volumes,
-                    ..Default::default()
-                }),
+                spec: Some(with_pod_extras(
+                    PodSpec {
+                        security_context: Some(PodSecurityContext {
+                            fs_group: Some(1000),
+                            ..Default::default()
+                        }),
+                        init_containers,
+                        containers: vec![container],
+                        volumes,
+                        image_pull_secrets: image_pull_secrets(&config.pod.image_pull_secrets),
+                        ..pod_scheduling(&Some(scheduling))
+                    },
+                    &config.pod,
+                )),
             },
             selector: LabelSelector {
                 match_labels: Some(BTreeMap::from([(
@@ -249,11 +603,175 @@ pub async fn statefulset(
                 )])),
                 ..Default::default()
             },
+            // The pods get stable DNS names in the headless service, used as bootnodes.
+            service_name: config.name.to_string(),
+            volume_claim_templates,
             ..Default::default()
         }),
-        status: Some(StatefulSetStatus::default()),
+        status: None,
     };
 
-    let pp = PostParams::default();
-    statefulsets.create(&pp, &node).await.map_err(|e| e.into())
+    let mut objects = vec![K8sObject::Service(service)];
+
+    if let Some(ref config_p2p_service) = config.p2p_service {
+        let p2p_service = p2p_service(&metadata, config, config_p2p_service);
+        objects.push(K8sObject::Service(p2p_service));
+    }
+
+    objects.push(K8sObject::ConfigMap(configmap));
+
+    for secret in [node_key_secret, session_keys_secret].into_iter().flatten() {
+        objects.push(K8sObject::Secret(secret));
+    }
+
+    objects.push(K8sObject::StatefulSet(node));
+
+    objects
+}
+
+/// The node key stored in the secret of the instance by an earlier create or apply.
+pub async fn stored_node_key(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    let secrets: Api<Secret> = Api::namespaced(client, namespace);
+    let secret = secrets.get_opt(&node_key_secret_name(name)).await?;
+
+    Ok(secret
+        .and_then(|x| x.data)
+        .and_then(|mut data| data.remove("node-key"))
+        .map(|x| String::from_utf8_lossy(&x.0).to_string()))
+}
+
+/// The chainspec secret is created without the tool, so it must exist before the node.
+pub async fn check_chainspec_secret(
+    client: Client,
+    namespace: &str,
+    chain_type: SugarfungeChainType,
+    config: &NodeConfig,
+) -> anyhow::Result<()> {
+    if chain_type == SugarfungeChainType::Testnet && config.chainspec_ext.is_none() {
+        let secrets: Api<Secret> = Api::namespaced(client, namespace);
+
+        if secrets.get_opt(&config.name).await?.is_none() {
+            return Err(anyhow::Error::msg(format!(
+                "The secret {} does not exist",
+                config.name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, node_key: Option<String>) -> NodeConfig {
+        NodeConfig {
+            name: name.to_string(),
+            node_key,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bootnodes_are_the_other_nodes_with_a_key() {
+        let nodes = [
+            node("sf-node-1", Some("00".repeat(31) + "01")),
+            node("sf-node-2", Some("00".repeat(31) + "01")),
+            node("sf-node-3", None),
+        ];
+
+        let expected = "/dns4/sf-node-1-0.sf-node-1/tcp/30334/p2p/12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp";
+
+        assert_eq!(bootnodes(&nodes[1], &nodes), [expected]);
+        assert_eq!(bootnodes(&nodes[2], &nodes).len(), 2);
+        assert!(bootnodes(&nodes[0], &nodes[..1]).is_empty());
+    }
+
+    #[test]
+    fn rpc_args_of_the_chains() {
+        assert_eq!(
+            rpc_args(&RpcConfig::for_chain(SugarfungeChainType::Local)),
+            [
+                "--rpc-methods=Unsafe",
+                "--unsafe-ws-external",
+                "--unsafe-rpc-external",
+                "--rpc-cors=all",
+            ]
+        );
+        assert_eq!(
+            rpc_args(&RpcConfig::for_chain(SugarfungeChainType::Testnet)),
+            ["--rpc-methods=Safe", "--ws-external"]
+        );
+    }
+
+    #[test]
+    fn rpc_args_of_the_optional_settings() {
+        let rpc = RpcConfig {
+            methods: RpcMethods::Auto,
+            cors: Some(vec![
+                "https://explorer.sugarfunge.dev".to_string(),
+                "http://localhost".to_string(),
+            ]),
+            max_connections: Some(500),
+            ws_external: false,
+            rpc_external: true,
+        };
+
+        assert_eq!(
+            rpc_args(&rpc),
+            [
+                "--rpc-methods=Auto",
+                "--rpc-external",
+                "--rpc-cors=https://explorer.sugarfunge.dev,http://localhost",
+                "--ws-max-connections=500",
+            ]
+        );
+    }
+
+    #[test]
+    fn public_addr_of_the_p2p_service() {
+        let mut config = node("sf-node", None);
+        assert_eq!(public_addr(&config), None);
+
+        config.p2p_service = Some(P2pServiceConfig {
+            service_type: P2pServiceType::NodePort,
+            node_port: Some(30334),
+            public_host: Some("node.sugarfunge.dev".to_string()),
+        });
+        assert_eq!(
+            public_addr(&config).as_deref(),
+            Some("/dns4/node.sugarfunge.dev/tcp/30334")
+        );
+
+        // The port of a NodePort service is only known in advance when it is set.
+        config.p2p_service = Some(P2pServiceConfig {
+            service_type: P2pServiceType::NodePort,
+            node_port: None,
+            public_host: Some("node.sugarfunge.dev".to_string()),
+        });
+        assert_eq!(public_addr(&config), None);
+
+        config.p2p_port = 30335;
+        config.p2p_service = Some(P2pServiceConfig {
+            service_type: P2pServiceType::LoadBalancer,
+            node_port: None,
+            public_host: Some("203.0.113.10".to_string()),
+        });
+        assert_eq!(
+            public_addr(&config).as_deref(),
+            Some("/ip4/203.0.113.10/tcp/30335")
+        );
+
+        config.p2p_service = Some(P2pServiceConfig {
+            service_type: P2pServiceType::LoadBalancer,
+            node_port: None,
+            public_host: None,
+        });
+        assert_eq!(public_addr(&config), None);
+    }
 }
@@ -0,0 +1,16 @@
+use kube::core::ObjectMeta;
+
+use crate::{
+    config::RegistryConfig,
+    utils::{docker_config_secret, labels, K8sObject},
+};
+
+pub fn manifests(config: &RegistryConfig) -> Vec<K8sObject> {
+    let metadata = ObjectMeta {
+        name: Some(config.name.to_string()),
+        labels: Some(labels(&config.name)),
+        ..Default::default()
+    };
+
+    vec![K8sObject::Secret(docker_config_secret(metadata, config))]
+}
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec, DeploymentStatus},
+        apps::v1::{Deployment, DeploymentSpec},
         core::v1::{
             ConfigMapEnvSource, Container, ContainerPort, EnvFromSource, PodSpec, PodTemplateSpec,
             ServicePort,
@@ -11,15 +11,14 @@ use k8s_openapi::{
     apimachinery::pkg::{apis::meta::v1::LabelSelector, util::intstr::IntOrString},
 };
 
-use kube::{
-    api::{Api, PostParams},
-    core::ObjectMeta,
-    Client,
-};
+use kube::core::ObjectMeta;
 
 use crate::{
     config::StatusConfig,
-    utils::{create_configmap, create_service, ServiceData},
+    utils::{
+        configmap, image_pull_secrets, labels, pod_scheduling, resource_requirements, service,
+        with_pod_extras, K8sObject, ServiceData,
+    },
 };
 
 fn container(config: StatusConfig) -> Container {
@@ -42,19 +41,15 @@ fn container(config: StatusConfig) -> Container {
         image_pull_policy: Some("IfNotPresent".to_string()),
         name: config.name.to_string(),
         ports: Some(vec![container_port]),
+        resources: resource_requirements(&config.pod.resources),
         ..Default::default()
     }
 }
 
-pub async fn deployment(namespace: &str, config: StatusConfig) -> anyhow::Result<Deployment> {
-    let client = Client::try_default().await?;
-
+pub fn manifests(config: &StatusConfig) -> Vec<K8sObject> {
     let metadata = ObjectMeta {
         name: Some(config.name.to_string()),
-        labels: Some(BTreeMap::from([(
-            "app.kubernetes.io/name".to_string(),
-            config.name.to_string(),
-        )])),
+        labels: Some(labels(&config.name)),
         ..Default::default()
     };
 
@@ -70,8 +65,7 @@ pub async fn deployment(namespace: &str, config: StatusConfig) -> anyhow::Result
         ..Default::default()
     };
 
-    let _service =
-        create_service(client.clone(), namespace, metadata.clone(), service_data).await?;
+    let service = service(metadata.clone(), service_data);
 
     let configmap_data = BTreeMap::from([
         ("PORT".to_string(), config.port.to_string()),
@@ -81,10 +75,7 @@ pub async fn deployment(namespace: &str, config: StatusConfig) -> anyhow::Result
         ),
     ]);
 
-    let _configmap =
-        create_configmap(client.clone(), namespace, metadata.clone(), configmap_data).await?;
-
-    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let configmap = configmap(metadata.clone(), configmap_data);
 
     let container = container(config.clone());
 
@@ -93,10 +84,14 @@ pub async fn deployment(namespace: &str, config: StatusConfig) -> anyhow::Result
         spec: Some(DeploymentSpec {
             template: PodTemplateSpec {
                 metadata: Some(metadata.clone()),
-                spec: Some(PodSpec {
-                    containers: vec![container],
-                    ..Default::default()
-                }),
+                spec: Some(with_pod_extras(
+                    PodSpec {
+                        containers: vec![container],
+                        image_pull_secrets: image_pull_secrets(&config.pod.image_pull_secrets),
+                        ..pod_scheduling(&config.pod.scheduling)
+                    },
+                    &config.pod,
+                )),
             },
             selector: LabelSelector {
                 match_labels: Some(BTreeMap::from([(
@@ -107,9 +102,12 @@ pub async fn deployment(namespace: &str, config: StatusConfig) -> anyhow::Result
             },
             ..Default::default()
         }),
-        status: Some(DeploymentStatus::default()),
+        status: None,
     };
 
-    let pp = PostParams::default();
-    deployments.create(&pp, &status).await.map_err(|e| e.into())
+    vec![
+        K8sObject::Service(service),
+        K8sObject::ConfigMap(configmap),
+        K8sObject::Deployment(status),
+    ]
 }
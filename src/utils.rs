@@ -1,15 +1,37 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fmt::Debug};
 
-use k8s_openapi::api::{
-    apps::v1::{Deployment, StatefulSet},
-    core::v1::{ConfigMap, Secret, Service, ServicePort, ServiceSpec},
-    networking::v1::Ingress,
+use derive_more::Display;
+use k8s_openapi::{
+    api::{
+        apps::v1::{Deployment, StatefulSet},
+        core::v1::{
+            ConfigMap, LocalObjectReference, PersistentVolumeClaim, PodSpec, PodTemplateSpec,
+            ResourceRequirements, Secret, Service, ServicePort, ServiceSpec,
+        },
+        networking::v1::Ingress,
+    },
+    apimachinery::pkg::api::resource::Quantity,
 };
 use kube::{
-    api::{DeleteParams, PostParams},
+    api::{DeleteParams, ListParams, Patch, PatchParams, PostParams},
+    config::{KubeConfigOptions, Kubeconfig},
     core::ObjectMeta,
-    Api, Client,
+    Api, Client, Config, Resource,
 };
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::{
+    PodConfig, RegistryConfig, ResourceQuantities, ResourcesConfig, SchedulingConfig,
+};
+
+/// Field manager used to own the fields of the objects applied by the tool.
+pub const FIELD_MANAGER: &str = "sugarfunge-k8s";
+
+/// Label set on every object created by the tool.
+pub const MANAGED_BY_LABEL: (&str, &str) = ("app.kubernetes.io/managed-by", FIELD_MANAGER);
+
+/// Label naming the stack of an object, after its prefix.
+pub const STACK_LABEL: &str = "app.kubernetes.io/part-of";
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct ServiceData {
@@ -19,7 +41,7 @@ pub struct ServiceData {
     pub service_type: Option<String>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Display)]
 pub enum K8sResource {
     Service,
     ConfigMap,
@@ -29,15 +51,139 @@ pub enum K8sResource {
     Ingress,
 }
 
-pub async fn create_service(
-    client: Client,
-    namespace: &str,
-    metadata: ObjectMeta,
-    service_data: ServiceData,
-) -> anyhow::Result<Service> {
-    let services: Api<Service> = Api::namespaced(client, namespace);
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum K8sObject {
+    Service(Service),
+    ConfigMap(ConfigMap),
+    Secret(Secret),
+    Deployment(Deployment),
+    StatefulSet(StatefulSet),
+    Ingress(Ingress),
+}
+
+impl K8sObject {
+    pub fn name(&self) -> String {
+        let metadata = match self {
+            K8sObject::Service(object) => &object.metadata,
+            K8sObject::ConfigMap(object) => &object.metadata,
+            K8sObject::Secret(object) => &object.metadata,
+            K8sObject::Deployment(object) => &object.metadata,
+            K8sObject::StatefulSet(object) => &object.metadata,
+            K8sObject::Ingress(object) => &object.metadata,
+        };
+
+        metadata.name.clone().unwrap_or_default()
+    }
+
+    pub fn resource_type(&self) -> K8sResource {
+        match self {
+            K8sObject::Service(_) => K8sResource::Service,
+            K8sObject::ConfigMap(_) => K8sResource::ConfigMap,
+            K8sObject::Secret(_) => K8sResource::Secret,
+            K8sObject::Deployment(_) => K8sResource::Deployment,
+            K8sObject::StatefulSet(_) => K8sResource::StatefulSet,
+            K8sObject::Ingress(_) => K8sResource::Ingress,
+        }
+    }
+
+    fn metadata_mut(&mut self) -> &mut ObjectMeta {
+        match self {
+            K8sObject::Service(object) => &mut object.metadata,
+            K8sObject::ConfigMap(object) => &mut object.metadata,
+            K8sObject::Secret(object) => &mut object.metadata,
+            K8sObject::Deployment(object) => &mut object.metadata,
+            K8sObject::StatefulSet(object) => &mut object.metadata,
+            K8sObject::Ingress(object) => &mut object.metadata,
+        }
+    }
+
+    /// The pod template of the workloads, `None` for the other objects.
+    pub fn pod_template_mut(&mut self) -> Option<&mut PodTemplateSpec> {
+        match self {
+            K8sObject::Deployment(object) => object.spec.as_mut().map(|x| &mut x.template),
+            K8sObject::StatefulSet(object) => object.spec.as_mut().map(|x| &mut x.template),
+            _ => None,
+        }
+    }
+
+    /// Adds the labels and annotations to the object and to its pod template, without
+    /// overriding the ones set by the tool.
+    pub fn add_metadata(
+        &mut self,
+        labels: &BTreeMap<String, String>,
+        annotations: &BTreeMap<String, String>,
+    ) {
+        add_metadata(self.metadata_mut(), labels, annotations);
+
+        if let Some(metadata) = self.pod_template_mut().and_then(|x| x.metadata.as_mut()) {
+            add_metadata(metadata, labels, annotations);
+        }
+
+        // The volume claims of the StatefulSet pods are created from these templates.
+        if let K8sObject::StatefulSet(StatefulSet {
+            spec: Some(spec), ..
+        }) = self
+        {
+            for claim in spec.volume_claim_templates.iter_mut().flatten() {
+                add_metadata(&mut claim.metadata, labels, annotations);
+            }
+        }
+    }
+}
+
+fn add_metadata(
+    metadata: &mut ObjectMeta,
+    labels: &BTreeMap<String, String>,
+    annotations: &BTreeMap<String, String>,
+) {
+    for (fields, values) in [
+        (&mut metadata.labels, labels),
+        (&mut metadata.annotations, annotations),
+    ] {
+        for (key, value) in values {
+            fields
+                .get_or_insert_with(BTreeMap::new)
+                .entry(key.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+}
+
+/// Builds the client from the given kubeconfig file and context, falling back to the
+/// defaults used by kubectl.
+pub async fn client(kubeconfig: Option<String>, context: Option<String>) -> anyhow::Result<Client> {
+    if kubeconfig.is_none() && context.is_none() {
+        return Ok(Client::try_default().await?);
+    }
+
+    let options = KubeConfigOptions {
+        context,
+        ..Default::default()
+    };
+
+    let config = match kubeconfig {
+        Some(path) => {
+            Config::from_custom_kubeconfig(Kubeconfig::read_from(path)?, &options).await?
+        }
+        None => Config::from_kubeconfig(&options).await?,
+    };
+
+    Ok(Client::try_from(config)?)
+}
 
-    let service = Service {
+pub fn labels(name: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app.kubernetes.io/name".to_string(), name.to_string()),
+        (
+            MANAGED_BY_LABEL.0.to_string(),
+            MANAGED_BY_LABEL.1.to_string(),
+        ),
+    ])
+}
+
+pub fn service(metadata: ObjectMeta, service_data: ServiceData) -> Service {
+    Service {
         metadata,
         spec: Some(ServiceSpec {
             ports: Some(vec![service_data.service_port]),
@@ -50,57 +196,406 @@ pub async fn create_service(
             ..Default::default()
         }),
         ..Default::default()
-    };
-
-    let pp = PostParams::default();
-    services.create(&pp, &service).await.map_err(|e| e.into())
+    }
 }
 
-pub async fn create_secret(
-    client: Client,
-    namespace: &str,
-    metadata: ObjectMeta,
-    data: BTreeMap<String, String>,
-) -> anyhow::Result<Secret> {
-    let secrets: Api<Secret> = Api::namespaced(client, namespace);
-
-    let secret = Secret {
+pub fn secret(metadata: ObjectMeta, data: BTreeMap<String, String>) -> Secret {
+    Secret {
         metadata,
         string_data: Some(data),
         ..Default::default()
-    };
+    }
+}
 
-    let pp = PostParams::default();
-    secrets.create(&pp, &secret).await.map_err(|e| e.into())
+/// Secret holding the credentials of a registry, to pull the images from it.
+pub fn docker_config_secret(metadata: ObjectMeta, config: &RegistryConfig) -> Secret {
+    let auth = base64::encode(format!("{}:{}", config.username, config.password));
+    let mut credentials = serde_json::json!({
+        "username": config.username,
+        "password": config.password,
+        "auth": auth,
+    });
+
+    if let Some(email) = &config.email {
+        credentials["email"] = email.to_string().into();
+    }
+
+    let docker_config = serde_json::json!({ "auths": { &config.server: credentials } });
+
+    Secret {
+        metadata,
+        type_: Some("kubernetes.io/dockerconfigjson".to_string()),
+        string_data: Some(BTreeMap::from([(
+            ".dockerconfigjson".to_string(),
+            docker_config.to_string(),
+        )])),
+        ..Default::default()
+    }
 }
 
-pub async fn create_configmap(
-    client: Client,
-    namespace: &str,
-    metadata: ObjectMeta,
-    data: BTreeMap<String, String>,
-) -> anyhow::Result<ConfigMap> {
-    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+/// References to the pull secrets with the given names.
+pub fn image_pull_secrets(names: &Option<Vec<String>>) -> Option<Vec<LocalObjectReference>> {
+    names.as_ref().map(|names| {
+        names
+            .iter()
+            .map(|name| LocalObjectReference {
+                name: Some(name.to_string()),
+            })
+            .collect()
+    })
+}
 
-    let config_map = ConfigMap {
+pub fn configmap(metadata: ObjectMeta, data: BTreeMap<String, String>) -> ConfigMap {
+    ConfigMap {
         data: Some(data),
         metadata,
         ..Default::default()
+    }
+}
+
+fn quantities(quantities: &ResourceQuantities) -> BTreeMap<String, Quantity> {
+    let mut map = BTreeMap::new();
+
+    for (name, quantity) in [
+        ("cpu", &quantities.cpu),
+        ("memory", &quantities.memory),
+        ("ephemeral-storage", &quantities.ephemeral_storage),
+    ] {
+        if let Some(quantity) = quantity {
+            map.insert(name.to_string(), Quantity(quantity.to_string()));
+        }
+    }
+
+    map
+}
+
+/// Requests and limits of a container, `None` leaves the container without resources.
+pub fn resource_requirements(config: &Option<ResourcesConfig>) -> Option<ResourceRequirements> {
+    config.as_ref().map(|config| ResourceRequirements {
+        requests: config.requests.as_ref().map(quantities),
+        limits: config.limits.as_ref().map(quantities),
+    })
+}
+
+/// Adds the extras of a component to its pod spec, the first container being the main one.
+pub fn with_pod_extras(mut spec: PodSpec, config: &PodConfig) -> PodSpec {
+    if let Some(container) = spec.containers.first_mut() {
+        if let Some(env) = config.extra_env.clone() {
+            container.env.get_or_insert_with(Vec::new).extend(env);
+        }
+
+        // After the generated args, so a repeated flag overrides the generated one.
+        if let Some(args) = config.extra_args.clone() {
+            container.args.get_or_insert_with(Vec::new).extend(args);
+        }
+
+        if let Some(volume_mounts) = config.extra_volume_mounts.clone() {
+            container
+                .volume_mounts
+                .get_or_insert_with(Vec::new)
+                .extend(volume_mounts);
+        }
+    }
+
+    if let Some(volumes) = config.extra_volumes.clone() {
+        spec.volumes.get_or_insert_with(Vec::new).extend(volumes);
+    }
+
+    if let Some(sidecars) = config.sidecars.clone() {
+        spec.containers.extend(sidecars);
+    }
+
+    spec
+}
+
+/// A pod spec with only the scheduling fields set, to fill the pod spec of a component.
+pub fn pod_scheduling(config: &Option<SchedulingConfig>) -> PodSpec {
+    let config = match config {
+        Some(config) => config.clone(),
+        None => return PodSpec::default(),
     };
 
+    PodSpec {
+        node_selector: config.node_selector,
+        tolerations: config.tolerations,
+        affinity: config.affinity,
+        topology_spread_constraints: config.topology_spread,
+        ..Default::default()
+    }
+}
+
+async fn create_object<K>(client: Client, namespace: &str, object: &K) -> anyhow::Result<K>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Serialize + Debug,
+{
+    let api: Api<K> = Api::namespaced(client, namespace);
     let pp = PostParams::default();
-    config_maps
-        .create(&pp, &config_map)
+    api.create(&pp, object).await.map_err(|e| e.into())
+}
+
+async fn delete_object<K>(client: Client, namespace: &str, name: &str) -> anyhow::Result<()>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let api: Api<K> = Api::namespaced(client, namespace);
+    api.delete(name, &DeleteParams::default()).await?;
+    Ok(())
+}
+
+async fn create_resource(
+    client: Client,
+    namespace: &str,
+    object: &K8sObject,
+) -> anyhow::Result<()> {
+    match object {
+        K8sObject::Service(service) => {
+            create_object(client, namespace, service).await?;
+        }
+        K8sObject::ConfigMap(configmap) => {
+            create_object(client, namespace, configmap).await?;
+        }
+        K8sObject::Secret(secret) => {
+            create_object(client, namespace, secret).await?;
+        }
+        K8sObject::Deployment(deployment) => {
+            create_object(client, namespace, deployment).await?;
+        }
+        K8sObject::StatefulSet(statefulset) => {
+            create_object(client, namespace, statefulset).await?;
+        }
+        K8sObject::Ingress(ingress) => {
+            create_object(client, namespace, ingress).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn rollback(client: Client, namespace: &str, created: Vec<K8sObject>) {
+    for object in created.iter().rev() {
+        let name = object.name();
+        let result = match object.resource_type() {
+            K8sResource::Service => {
+                delete_object::<Service>(client.clone(), namespace, &name).await
+            }
+            K8sResource::ConfigMap => {
+                delete_object::<ConfigMap>(client.clone(), namespace, &name).await
+            }
+            K8sResource::Secret => delete_object::<Secret>(client.clone(), namespace, &name).await,
+            K8sResource::Deployment => {
+                delete_object::<Deployment>(client.clone(), namespace, &name).await
+            }
+            K8sResource::StatefulSet => {
+                delete_object::<StatefulSet>(client.clone(), namespace, &name).await
+            }
+            K8sResource::Ingress => {
+                delete_object::<Ingress>(client.clone(), namespace, &name).await
+            }
+        };
+
+        match result {
+            Ok(()) => println!("{}/{}: rolled back", object.resource_type(), name),
+            Err(e) => println!(
+                "{}/{}: failed to roll back: {}",
+                object.resource_type(),
+                name,
+                e
+            ),
+        }
+    }
+}
+
+/// Creates the objects in order. When one of them fails, the objects already created are
+/// deleted again unless `keep_on_failure` is set, so the next create does not fail with
+/// AlreadyExists.
+pub async fn create_resources(
+    client: Client,
+    namespace: &str,
+    objects: Vec<K8sObject>,
+    keep_on_failure: bool,
+) -> anyhow::Result<()> {
+    let mut created: Vec<K8sObject> = vec![];
+
+    for object in objects {
+        if let Err(e) = create_resource(client.clone(), namespace, &object).await {
+            if !keep_on_failure {
+                rollback(client, namespace, created).await;
+            }
+            return Err(e);
+        }
+
+        created.push(object);
+    }
+
+    Ok(())
+}
+
+async fn apply_object<K>(client: Client, namespace: &str, object: &K) -> anyhow::Result<K>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Serialize + Debug,
+{
+    let api: Api<K> = Api::namespaced(client, namespace);
+    let name = object.meta().name.clone().unwrap_or_default();
+    let pp = PatchParams::apply(FIELD_MANAGER).force();
+    api.patch(&name, &pp, &Patch::Apply(object))
         .await
         .map_err(|e| e.into())
 }
 
+/// Server-side applies the objects, creating the missing ones and updating the existing ones.
+pub async fn apply_resources(
+    client: Client,
+    namespace: &str,
+    objects: Vec<K8sObject>,
+) -> anyhow::Result<()> {
+    for object in objects {
+        match object {
+            K8sObject::Service(service) => {
+                apply_object(client.clone(), namespace, &service).await?;
+            }
+            K8sObject::ConfigMap(configmap) => {
+                apply_object(client.clone(), namespace, &configmap).await?;
+            }
+            K8sObject::Secret(secret) => {
+                apply_object(client.clone(), namespace, &secret).await?;
+            }
+            K8sObject::Deployment(deployment) => {
+                apply_object(client.clone(), namespace, &deployment).await?;
+            }
+            K8sObject::StatefulSet(statefulset) => {
+                apply_object(client.clone(), namespace, &statefulset).await?;
+            }
+            K8sObject::Ingress(ingress) => {
+                apply_object(client.clone(), namespace, &ingress).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_object<K>(client: Client, namespace: &str, name: &str) -> anyhow::Result<Option<K>>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let api: Api<K> = Api::namespaced(client, namespace);
+    api.get_opt(name).await.map_err(|e| e.into())
+}
+
+pub async fn get_resource(
+    client: Client,
+    namespace: &str,
+    resource_type: K8sResource,
+    name: &str,
+) -> anyhow::Result<Option<K8sObject>> {
+    let object = match resource_type {
+        K8sResource::Service => get_object(client, namespace, name)
+            .await?
+            .map(K8sObject::Service),
+        K8sResource::ConfigMap => get_object(client, namespace, name)
+            .await?
+            .map(K8sObject::ConfigMap),
+        K8sResource::Secret => get_object(client, namespace, name)
+            .await?
+            .map(K8sObject::Secret),
+        K8sResource::Deployment => get_object(client, namespace, name)
+            .await?
+            .map(K8sObject::Deployment),
+        K8sResource::StatefulSet => get_object(client, namespace, name)
+            .await?
+            .map(K8sObject::StatefulSet),
+        K8sResource::Ingress => get_object(client, namespace, name)
+            .await?
+            .map(K8sObject::Ingress),
+    };
+
+    Ok(object)
+}
+
+/// Selects the objects managed by the tool carrying the labels of the stack. Without stack labels,
+/// the objects of the stacks with a prefix are left out.
+fn managed_selector(stack_labels: &BTreeMap<String, String>) -> String {
+    let mut selector = format!("{}={}", MANAGED_BY_LABEL.0, MANAGED_BY_LABEL.1);
+
+    if stack_labels.is_empty() {
+        selector.push_str(&format!(",!{}", STACK_LABEL));
+    }
+    for (key, value) in stack_labels {
+        selector.push_str(&format!(",{}={}", key, value));
+    }
+
+    selector
+}
+
+async fn list_managed_objects<K>(
+    client: Client,
+    namespace: &str,
+    selector: &str,
+) -> anyhow::Result<Vec<K>>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let api: Api<K> = Api::namespaced(client, namespace);
+    let lp = ListParams::default().labels(selector);
+    Ok(api.list(&lp).await?.items)
+}
+
+/// Lists the objects in the namespace managed by the tool for the stack with the given labels.
+pub async fn list_managed_resources(
+    client: Client,
+    namespace: &str,
+    stack_labels: &BTreeMap<String, String>,
+) -> anyhow::Result<Vec<K8sObject>> {
+    let selector = managed_selector(stack_labels);
+    let selector = selector.as_str();
+    let mut objects: Vec<K8sObject> = vec![];
+
+    for service in list_managed_objects(client.clone(), namespace, selector).await? {
+        objects.push(K8sObject::Service(service));
+    }
+    for configmap in list_managed_objects(client.clone(), namespace, selector).await? {
+        objects.push(K8sObject::ConfigMap(configmap));
+    }
+    for secret in list_managed_objects(client.clone(), namespace, selector).await? {
+        objects.push(K8sObject::Secret(secret));
+    }
+    for deployment in list_managed_objects(client.clone(), namespace, selector).await? {
+        objects.push(K8sObject::Deployment(deployment));
+    }
+    for statefulset in list_managed_objects(client.clone(), namespace, selector).await? {
+        objects.push(K8sObject::StatefulSet(statefulset));
+    }
+    for ingress in list_managed_objects(client, namespace, selector).await? {
+        objects.push(K8sObject::Ingress(ingress));
+    }
+
+    Ok(objects)
+}
+
+/// Serializes the objects as a multi-document YAML stream.
+pub fn to_yaml(objects: &[K8sObject]) -> anyhow::Result<String> {
+    let mut output = String::new();
+
+    for object in objects {
+        let document = serde_yaml::to_string(object)?;
+        if !document.starts_with("---") {
+            output.push_str("---\n");
+        }
+        output.push_str(&document);
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+
+    Ok(output)
+}
+
 pub async fn delete_resources(
+    client: Client,
     namespace: &str,
     name: &str,
     resources: Vec<K8sResource>,
 ) -> anyhow::Result<()> {
-    let client = Client::try_default().await?;
     let dp = DeleteParams::default();
 
     for resource_type in resources {
@@ -146,3 +641,40 @@ pub async fn delete_resources(
 
     Ok(())
 }
+
+/// Deletes the volume claims of the StatefulSet pods of the instance, which Kubernetes keeps
+/// after the StatefulSet is deleted.
+pub async fn delete_volume_claims(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> anyhow::Result<()> {
+    let claims: Api<PersistentVolumeClaim> = Api::namespaced(client, namespace);
+    let lp = ListParams::default().labels(&format!("app.kubernetes.io/name={}", name));
+
+    claims
+        .delete_collection(&DeleteParams::default(), &lp)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn managed_selector_of_the_stack() {
+        assert_eq!(
+            managed_selector(&BTreeMap::new()),
+            "app.kubernetes.io/managed-by=sugarfunge-k8s,!app.kubernetes.io/part-of"
+        );
+        assert_eq!(
+            managed_selector(&BTreeMap::from([
+                (STACK_LABEL.to_string(), "team-a".to_string()),
+                ("team".to_string(), "a".to_string()),
+            ])),
+            "app.kubernetes.io/managed-by=sugarfunge-k8s,app.kubernetes.io/part-of=team-a,team=a"
+        );
+    }
+}